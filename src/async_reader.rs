@@ -0,0 +1,405 @@
+use std::{cmp, future, io, pin::Pin, task::{Context, Poll}};
+use futures_io::{AsyncRead, AsyncSeek};
+use crate::*;
+
+async fn read_exact_at<F: AsyncRead + AsyncSeek + Unpin>(file: &mut F, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+	future::poll_fn(|cx| Pin::new(&mut *file).poll_seek(cx, io::SeekFrom::Start(offset))).await?;
+	let mut filled = 0;
+	while filled < buf.len() {
+		let n = future::poll_fn(|cx| Pin::new(&mut *file).poll_read(cx, &mut buf[filled..])).await?;
+		if n == 0 {
+			return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+		}
+		filled += n;
+	}
+	Ok(())
+}
+
+/// Async directory iterator.
+pub struct AsyncReadIter<'a, F> {
+	async_reader: &'a mut AsyncReader<F>,
+	start: u32,
+	end: u32,
+}
+impl<'a, F: AsyncRead + AsyncSeek + Unpin> AsyncReadIter<'a, F> {
+	/// Reads and decrypts the next descriptor.
+	pub async fn next(&mut self) -> io::Result<Option<Descriptor>> {
+		if self.start >= self.end {
+			return Ok(None);
+		}
+		let desc = self.async_reader.read_desc(self.start as usize).await?;
+		self.start = directory::next_sibling(&desc, self.start as usize, self.end as usize) as u32;
+		Ok(Some(desc))
+	}
+}
+
+/// Reads a PAK file from an async stream with on-the-fly decryption.
+///
+/// Works with any stream implementing the `futures-io` traits.
+/// Tokio streams can be adapted with the compatibility layer found in `tokio-util`.
+///
+/// The directory is read and decrypted one descriptor at the time.
+pub struct AsyncReader<F> {
+	file: F,
+	key: Key,
+	info: InfoHeader,
+}
+impl<F: AsyncRead + AsyncSeek + Unpin> AsyncReader<F> {
+	/// Reads the header of the PAK file.
+	///
+	/// Returns an error converted from [`Error`](enum.Error.html) if the stream does not encode a PAK file.
+	pub async fn new(mut file: F, key: &Key) -> io::Result<AsyncReader<F>> {
+		let mut header = Header::zeroed();
+		read_exact_at(&mut file, 0, header.as_bytes_mut()).await?;
+		let info = crypt::decrypt_header(&header, key);
+		// The size of the stream is not known in advance, check everything but the directory range
+		error::check_info(&info, usize::MAX)?;
+		Ok(AsyncReader { file, key: *key, info })
+	}
+	/// Unwraps the underlying stream.
+	pub fn into_inner(self) -> F {
+		self.file
+	}
+	/// Returns if this AsyncReader contains no files or directories.
+	pub fn is_empty(&self) -> bool {
+		self.info.directory.size == 0
+	}
+	async fn read_desc(&mut self, index: usize) -> io::Result<Descriptor> {
+		let mut desc = Descriptor::zeroed();
+		let offset = (self.info.directory.offset as u64 + (index * Descriptor::BLOCKS_LEN) as u64) * BLOCK_SIZE as u64;
+		read_exact_at(&mut self.file, offset, desc.as_bytes_mut()).await?;
+		let nonce = crypt::counter(&self.info.directory.nonce, index * Descriptor::BLOCKS_LEN);
+		Ok(crypt::decrypt_desc(&desc, &nonce, &self.key))
+	}
+	/// Finds a descriptor by its path.
+	pub async fn find(&mut self, path: &[u8]) -> io::Result<Option<Descriptor>> {
+		self.find_range(0, self.info.directory.size as usize, path).await
+	}
+	/// Finds a descriptor by its path starting from the given root directory.
+	pub async fn find_sub(&mut self, root: &Descriptor, path: &[u8]) -> io::Result<Option<Descriptor>> {
+		let range = root.section.range_usize();
+		if range.start > range.end || range.end > self.info.directory.size as usize {
+			return Ok(None);
+		}
+		self.find_range(range.start, range.end, path).await
+	}
+	// Async mirror of `directory::try_find_by`, changes to the traversal must be made in both
+	async fn find_range(&mut self, mut i: usize, mut end: usize, path: &[u8]) -> io::Result<Option<Descriptor>> {
+		// Invalid paths are never found
+		let normalized = match path::normalize(path) {
+			Ok(path) => path,
+			Err(_) => return Ok(None),
+		};
+		let mut path = &*normalized;
+		let sorted = self.info.is_sorted();
+		while i < end {
+			let desc = self.read_desc(i).await?;
+			// The remaining siblings are files sorted by name
+			if sorted && !desc.is_dir() {
+				let name = match directory::sorted_file_name(path) {
+					Some(name) => name,
+					None => return Ok(None),
+				};
+				let (mut lo, mut hi) = (i, end);
+				while lo < hi {
+					let mid = lo + (hi - lo) / 2;
+					let desc = self.read_desc(mid).await?;
+					match desc.name().cmp(name) {
+						cmp::Ordering::Less => lo = mid + 1,
+						cmp::Ordering::Greater => hi = mid,
+						cmp::Ordering::Equal => return Ok(Some(desc)),
+					}
+				}
+				return Ok(None);
+			}
+			let next_i = directory::next_sibling(&desc, i, end);
+			if let Some(tail) = directory::name_eq(&desc, path) {
+				// Exactly matching descriptor found
+				if tail.is_empty() {
+					return Ok(Some(desc));
+				}
+				// Continue traversing directory descriptor
+				if desc.is_dir() {
+					path = tail;
+					i += 1;
+					end = next_i;
+					continue;
+				}
+			}
+			else if sorted && directory::name_cmp(&desc, path) == cmp::Ordering::Greater && directory::sorted_file_name(path).is_none() {
+				// All the remaining directories are greater and files cannot contain the rest of the path
+				break;
+			}
+			// Advance the iteration
+			i = next_i;
+		}
+		// No descriptor with this path found
+		Ok(None)
+	}
+	/// Returns if the descriptor is a valid file.
+	///
+	/// Unlike the `MemoryReader` the section is not checked against the size of the stream.
+	pub fn is_valid_file(&self, desc: &Descriptor) -> bool {
+		desc.content_type != 0 &&
+		desc.section.offset >= Header::BLOCKS_LEN as u32 &&
+		desc.section.offset.checked_add(desc.section.size).is_some() &&
+		bytes2blocks(desc.content_size) <= desc.section.size
+	}
+	/// Decrypts the contents of the given file descriptor.
+	///
+	/// Returns `InvalidInput` if the descriptor is not a valid file.
+	pub async fn read_data(&mut self, desc: &Descriptor) -> io::Result<Vec<u8>> {
+		let mut bytes = vec![0; desc.content_size as usize];
+		self.read_into(desc, 0, &mut bytes).await?;
+		Ok(bytes)
+	}
+	/// Decrypts the contents of the given file descriptor into the dest buffer.
+	/// Given a byte offset into the file where to start decrypting.
+	///
+	/// Returns `InvalidInput` if the descriptor is not a valid file or the range is outside the file's section.
+	pub async fn read_into(&mut self, desc: &Descriptor, byte_offset: usize, dest: &mut [u8]) -> io::Result<()> {
+		let byte_end = match byte_offset.checked_add(dest.len()) {
+			Some(byte_end) if self.is_valid_file(desc) && byte_end <= desc.section.size as usize * BLOCK_SIZE => byte_end,
+			_ => return Err(io::Error::from(io::ErrorKind::InvalidInput)),
+		};
+		if dest.is_empty() {
+			return Ok(());
+		}
+		// Read the encrypted blocks covering the requested range
+		let block_start = byte_offset / BLOCK_SIZE;
+		let block_end = byte_end.div_ceil(BLOCK_SIZE);
+		let mut blocks = vec![Block::default(); block_end - block_start];
+		let offset = (desc.section.offset as u64 + block_start as u64) * BLOCK_SIZE as u64;
+		read_exact_at(&mut self.file, offset, blocks.as_bytes_mut()).await?;
+		let nonce = crypt::counter(&desc.section.nonce, block_start);
+		crypt::decrypt_data(&blocks[..], &nonce, &self.key, byte_offset - block_start * BLOCK_SIZE, dest);
+		Ok(())
+	}
+	/// Opens the given file descriptor for streaming its decrypted contents.
+	///
+	/// Returns `None` if the descriptor is not a valid file.
+	pub fn open(&mut self, desc: &Descriptor) -> Option<AsyncFileReader<'_, F>> {
+		if !self.is_valid_file(desc) {
+			return None;
+		}
+		Some(AsyncFileReader {
+			file: &mut self.file,
+			key: self.key,
+			desc: *desc,
+			pos: 0,
+			state: State::Idle,
+			raw: Vec::new(),
+			buf: Vec::new(),
+			buf_start: 0,
+		})
+	}
+	pub fn iter(&mut self, desc: &Descriptor) -> AsyncReadIter<'_, F> {
+		AsyncReadIter {
+			start: desc.section.offset,
+			end: desc.section.offset.saturating_add(desc.section.size),
+			async_reader: self,
+		}
+	}
+}
+
+// Number of blocks read and decrypted at the time by the async file handle
+const CHUNK_BLOCKS: usize = 256;
+
+enum State {
+	Idle,
+	Seeking,
+	Reading(usize),
+}
+
+/// Async handle for reading the contents of a file in a PAK file.
+///
+/// Implements `AsyncRead` and `AsyncSeek` and decrypts the contents in chunks as they are read.
+pub struct AsyncFileReader<'a, F> {
+	file: &'a mut F,
+	key: Key,
+	desc: Descriptor,
+	pos: u64,
+	state: State,
+	// Encrypted and decrypted bytes of the current chunk
+	raw: Vec<u8>,
+	buf: Vec<u8>,
+	buf_start: u64,
+}
+impl<'a, F> AsyncFileReader<'a, F> {
+	/// Returns the size of the file in bytes.
+	#[inline]
+	pub fn len(&self) -> u64 {
+		self.desc.content_size as u64
+	}
+	/// Returns if the file is empty.
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.desc.content_size == 0
+	}
+}
+impl<'a, F: AsyncRead + AsyncSeek + Unpin> AsyncRead for AsyncFileReader<'a, F> {
+	fn poll_read(self: Pin<&mut Self>, cx: &mut Context, out: &mut [u8]) -> Poll<io::Result<usize>> {
+		let this = self.get_mut();
+		let size = this.desc.content_size as u64;
+		if this.pos >= size || out.is_empty() {
+			return Poll::Ready(Ok(0));
+		}
+		// Fetch the chunk containing the current position
+		let in_buf = this.pos >= this.buf_start && this.pos < this.buf_start + this.buf.len() as u64;
+		if !in_buf {
+			let block_start = this.pos / BLOCK_SIZE as u64;
+			let chunk_start = block_start * BLOCK_SIZE as u64;
+			let chunk_len = cmp::min(size - chunk_start, (CHUNK_BLOCKS * BLOCK_SIZE) as u64) as usize;
+			let raw_len = bytes2blocks(chunk_len as u32) as usize * BLOCK_SIZE;
+			loop {
+				match this.state {
+					State::Idle => {
+						this.state = State::Seeking;
+					},
+					State::Seeking => {
+						let offset = this.desc.section.offset as u64 * BLOCK_SIZE as u64 + chunk_start;
+						match Pin::new(&mut *this.file).poll_seek(cx, io::SeekFrom::Start(offset)) {
+							Poll::Ready(Ok(_)) => {},
+							Poll::Ready(Err(err)) => {
+								this.state = State::Idle;
+								return Poll::Ready(Err(err));
+							},
+							Poll::Pending => return Poll::Pending,
+						}
+						this.raw.resize(raw_len, 0);
+						this.state = State::Reading(0);
+					},
+					State::Reading(filled) if filled == raw_len => {
+						this.buf.resize(chunk_len, 0);
+						let nonce = crypt::counter(&this.desc.section.nonce, block_start as usize);
+						crypt::decrypt_data(&this.raw[..], &nonce, &this.key, 0, &mut this.buf);
+						this.buf_start = chunk_start;
+						this.state = State::Idle;
+						break;
+					},
+					State::Reading(filled) => {
+						match Pin::new(&mut *this.file).poll_read(cx, &mut this.raw[filled..raw_len]) {
+							Poll::Ready(Ok(0)) => {
+								this.state = State::Idle;
+								return Poll::Ready(Err(io::Error::from(io::ErrorKind::UnexpectedEof)));
+							},
+							Poll::Ready(Ok(n)) => this.state = State::Reading(filled + n),
+							Poll::Ready(Err(err)) => {
+								this.state = State::Idle;
+								return Poll::Ready(Err(err));
+							},
+							Poll::Pending => return Poll::Pending,
+						}
+					},
+				}
+			}
+		}
+		// Copy the decrypted bytes out of the chunk
+		let offset = (this.pos - this.buf_start) as usize;
+		let n = cmp::min(out.len(), this.buf.len() - offset);
+		out[..n].copy_from_slice(&this.buf[offset..offset + n]);
+		this.pos += n as u64;
+		Poll::Ready(Ok(n))
+	}
+}
+impl<'a, F: AsyncRead + AsyncSeek + Unpin> AsyncSeek for AsyncFileReader<'a, F> {
+	fn poll_seek(self: Pin<&mut Self>, _cx: &mut Context, pos: io::SeekFrom) -> Poll<io::Result<u64>> {
+		let this = self.get_mut();
+		// Abandon any partially read chunk
+		this.state = State::Idle;
+		let result = file_reader::seek_pos(this.pos, this.desc.content_size as u64, pos);
+		if let Ok(pos) = result {
+			this.pos = pos;
+		}
+		Poll::Ready(result)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{future::Future, task::Waker};
+	use super::*;
+
+	// Stream which returns short reads and is pending every other poll
+	struct Stream {
+		bytes: Vec<u8>,
+		pos: usize,
+		pending: bool,
+	}
+	impl AsyncRead for Stream {
+		fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+			self.pending = !self.pending;
+			if self.pending {
+				cx.waker().wake_by_ref();
+				return Poll::Pending;
+			}
+			let n = cmp::min(cmp::min(buf.len(), 7), self.bytes.len() - self.pos);
+			buf[..n].copy_from_slice(&self.bytes[self.pos..self.pos + n]);
+			self.pos += n;
+			Poll::Ready(Ok(n))
+		}
+	}
+	impl AsyncSeek for Stream {
+		fn poll_seek(mut self: Pin<&mut Self>, _cx: &mut Context, pos: io::SeekFrom) -> Poll<io::Result<u64>> {
+			let pos = file_reader::seek_pos(self.pos as u64, self.bytes.len() as u64, pos)?;
+			self.pos = pos as usize;
+			Poll::Ready(Ok(pos))
+		}
+	}
+
+	fn block_on<T>(fut: impl Future<Output = T>) -> T {
+		let mut fut = Box::pin(fut);
+		let mut cx = Context::from_waker(Waker::noop());
+		loop {
+			if let Poll::Ready(value) = fut.as_mut().poll(&mut cx) {
+				return value;
+			}
+		}
+	}
+
+	async fn read_to_end<R: AsyncRead + Unpin>(file: &mut R) -> io::Result<Vec<u8>> {
+		let mut result = Vec::new();
+		let mut buf = [0; 100];
+		loop {
+			let n = future::poll_fn(|cx| Pin::new(&mut *file).poll_read(cx, &mut buf)).await?;
+			if n == 0 {
+				return Ok(result);
+			}
+			result.extend_from_slice(&buf[..n]);
+		}
+	}
+
+	#[test]
+	fn test_async_reader() {
+		let key = &[13, 42];
+		let large = (0..5000).map(|i| i as u8).collect::<Vec<u8>>();
+		let mut edit = MemoryEditor::new();
+		edit.create_file(b"a/small", b"Hello async world!", key);
+		edit.create_file(b"a/large", &large, key);
+		let (blocks, _) = edit.finish(key);
+		let stream = Stream { bytes: blocks.as_bytes().to_vec(), pos: 0, pending: false };
+
+		block_on(async {
+			let mut reader = AsyncReader::new(stream, key).await.unwrap();
+			let small = reader.find(b"a/small").await.unwrap().unwrap();
+			assert_eq!(reader.read_data(&small).await.unwrap(), b"Hello async world!");
+			let mut dest = [0; 5];
+			reader.read_into(&small, 6, &mut dest).await.unwrap();
+			assert_eq!(&dest, b"async");
+
+			let dir = reader.find(b"a").await.unwrap().unwrap();
+			let mut names = Vec::new();
+			let mut iter = reader.iter(&dir);
+			while let Some(desc) = iter.next().await.unwrap() {
+				names.push(desc.name().to_vec());
+			}
+			assert_eq!(names, [b"small".to_vec(), b"large".to_vec()]);
+
+			let large_desc = reader.find_sub(&dir, b"large").await.unwrap().unwrap();
+			let mut file = reader.open(&large_desc).unwrap();
+			assert_eq!(read_to_end(&mut file).await.unwrap(), large);
+			future::poll_fn(|cx| Pin::new(&mut file).poll_seek(cx, io::SeekFrom::End(-10))).await.unwrap();
+			assert_eq!(read_to_end(&mut file).await.unwrap(), &large[large.len() - 10..]);
+		});
+	}
+}
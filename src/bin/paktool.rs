@@ -5,9 +5,146 @@ paktool new key=000102030405060708090A0B0C0D0E0F FILE.PAK
 paktool add key=000102030405060708090A0B0C0D0E0F FILE.PAK path=path/here.txt < file.txt
 paktool rm key=000102030405060708090A0B0C0D0E0F FILE.PAK path=path/here.txt
 paktool gc key=000102030405060708090A0B0C0D0E0F FILE.PAK
+paktool merge key=000102030405060708090A0B0C0D0E0F OUT.PAK conflict=last key=101112131415161718191A1B1C1D1E1F A.PAK B.PAK
+paktool diff key=000102030405060708090A0B0C0D0E0F OLD.PAK key=101112131415161718191A1B1C1D1E1F NEW.PAK
+paktool patch key=000102030405060708090A0B0C0D0E0F OLD.PAK key=101112131415161718191A1B1C1D1E1F NEW.PAK PATCH
+paktool apply key=000102030405060708090A0B0C0D0E0F OLD.PAK key=101112131415161718191A1B1C1D1E1F PATCH OUT.PAK
 
 */
 
-fn main() {
+use std::{env, fs, process};
+use dataview::Pod;
+
+const HELP: &str = "\
+paktool help
+paktool merge key=KEY OUT.PAK [conflict=last|first|error] [key=KEY] IN.PAK...
+paktool diff key=KEY OLD.PAK [key=KEY] NEW.PAK
+paktool patch key=KEY OLD.PAK [key=KEY] NEW.PAK PATCH
+paktool apply key=KEY OLD.PAK [key=KEY] PATCH OUT.PAK
+
+Keys are 32 hex digits, every key applies to the PAK files following it.
+The output file of merge uses the first key.
+";
+
+fn parse_key(hex: &str) -> Result<pak::Key, String> {
+	let parse = |half: &str| u64::from_str_radix(half, 16).ok();
+	match (hex.len() == 32 && hex.is_ascii()).then(|| (parse(&hex[..16]), parse(&hex[16..]))) {
+		Some((Some(hi), Some(lo))) => Ok([hi, lo]),
+		_ => Err(format!("invalid key: {}", hex)),
+	}
+}
 
+fn read_pak(path: &str, key: &pak::Key) -> Result<Vec<pak::Block>, String> {
+	let file = fs::File::open(path).map_err(|err| format!("{}: {}", path, err))?;
+	pak::read(file, key).map_err(|err| format!("{}: {}", path, err))
+}
+
+fn write_pak(path: &str, blocks: &[pak::Block]) -> Result<(), String> {
+	fs::write(path, blocks.as_bytes()).map_err(|err| format!("{}: {}", path, err))
+}
+
+fn merge(args: &[String]) -> Result<(), String> {
+	let mut key = None;
+	let mut conflict = pak::merge::Conflict::LastWins;
+	let mut output = None;
+	let mut inputs = Vec::new();
+	for arg in args {
+		if let Some(hex) = arg.strip_prefix("key=") {
+			key = Some(parse_key(hex)?);
+		}
+		else if let Some(policy) = arg.strip_prefix("conflict=") {
+			conflict = match policy {
+				"last" => pak::merge::Conflict::LastWins,
+				"first" => pak::merge::Conflict::FirstWins,
+				"error" => pak::merge::Conflict::Error,
+				_ => return Err(format!("invalid conflict policy: {}", policy)),
+			};
+		}
+		else {
+			let key = key.ok_or_else(|| format!("missing key for {}", arg))?;
+			if output.is_none() {
+				output = Some((arg, key));
+			}
+			else {
+				inputs.push((read_pak(arg, &key)?, key));
+			}
+		}
+	}
+	let (output, key) = output.ok_or("missing output file")?;
+	let inputs: Vec<(&[pak::Block], &pak::Key)> = inputs.iter().map(|(blocks, key)| (&blocks[..], key)).collect();
+	let (blocks, _) = pak::merge::merge(&inputs, &key, conflict).map_err(|err| err.to_string())?;
+	write_pak(output, &blocks)
+}
+
+// Pairs every file argument with the last key before it
+fn keyed_files(args: &[String]) -> Result<Vec<(&str, pak::Key)>, String> {
+	let mut key = None;
+	let mut files = Vec::new();
+	for arg in args {
+		if let Some(hex) = arg.strip_prefix("key=") {
+			key = Some(parse_key(hex)?);
+		}
+		else {
+			files.push((&arg[..], key.ok_or_else(|| format!("missing key for {}", arg))?));
+		}
+	}
+	Ok(files)
+}
+
+fn diff(args: &[String]) -> Result<(), String> {
+	let [(old, old_key), (new, new_key)] = keyed_files(args)?[..] else {
+		return Err("expected two PAK files".to_string());
+	};
+	let (old, new) = (read_pak(old, &old_key)?, read_pak(new, &new_key)?);
+	let old = pak::MemoryReader::try_from_blocks(&old, &old_key).map_err(|err| err.to_string())?;
+	let new = pak::MemoryReader::try_from_blocks(&new, &new_key).map_err(|err| err.to_string())?;
+	for change in pak::diff::diff(&old, &new).map_err(|err| err.to_string())? {
+		println!("{}", change);
+	}
+	Ok(())
+}
+
+fn patch(args: &[String]) -> Result<(), String> {
+	let [(old, old_key), (new, new_key), (output, _)] = keyed_files(args)?[..] else {
+		return Err("expected two PAK files and the patch file".to_string());
+	};
+	let (old, new) = (read_pak(old, &old_key)?, read_pak(new, &new_key)?);
+	let old = pak::MemoryReader::try_from_blocks(&old, &old_key).map_err(|err| err.to_string())?;
+	let new = pak::MemoryReader::try_from_blocks(&new, &new_key).map_err(|err| err.to_string())?;
+	let patch = pak::patch::create(&old, &new).map_err(|err| err.to_string())?;
+	write_pak(output, &patch)
+}
+
+fn apply(args: &[String]) -> Result<(), String> {
+	let [(old, old_key), (patch, key), (output, _)] = keyed_files(args)?[..] else {
+		return Err("expected the PAK file, the patch file and the output file".to_string());
+	};
+	let old = read_pak(old, &old_key)?;
+	let old = pak::MemoryReader::try_from_blocks(&old, &old_key).map_err(|err| err.to_string())?;
+	let bytes = fs::read(patch).map_err(|err| format!("{}: {}", patch, err))?;
+	if bytes.len() % pak::BLOCK_SIZE != 0 {
+		return Err(format!("{}: {}", patch, pak::Error::BadPatch));
+	}
+	let mut patch = vec![pak::Block::default(); bytes.len() / pak::BLOCK_SIZE];
+	patch.as_bytes_mut().copy_from_slice(&bytes);
+	let (blocks, _) = pak::patch::apply(&old, &patch, &key).map_err(|err| err.to_string())?;
+	write_pak(output, &blocks)
+}
+
+fn main() {
+	let args: Vec<String> = env::args().skip(1).collect();
+	let result = match args.first().map(String::as_str) {
+		Some("merge") => merge(&args[1..]),
+		Some("diff") => diff(&args[1..]),
+		Some("patch") => patch(&args[1..]),
+		Some("apply") => apply(&args[1..]),
+		_ => {
+			print!("{}", HELP);
+			Ok(())
+		},
+	};
+	if let Err(err) = result {
+		eprintln!("paktool: {}", err);
+		process::exit(1);
+	}
 }
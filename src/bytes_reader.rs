@@ -0,0 +1,256 @@
+use crate::*;
+
+fn read_directory<'a>(bytes: &'a [u8], info: &InfoHeader) -> &'a [u8] {
+	let dir_offset = info.directory.offset as usize * BLOCK_SIZE;
+	let dir_size = info.directory.size as usize * Descriptor::BLOCKS_LEN * BLOCK_SIZE;
+	match bytes.get(dir_offset..dir_offset + dir_size) {
+		Some(directory) => directory,
+		None => &[],
+	}
+}
+
+/// Bytes directory iterator.
+#[derive(Clone)]
+pub struct BytesReadIter<'a> {
+	bytes_reader: BytesReader<'a>,
+	start: u32,
+	end: u32,
+}
+impl<'a> Iterator for BytesReadIter<'a> {
+	type Item = Descriptor;
+	fn next(&mut self) -> Option<Descriptor> {
+		if self.start >= self.end {
+			return None;
+		}
+		let desc = self.bytes_reader.decrypt_desc(self.start as usize);
+		self.start = directory::next_sibling(&desc, self.start as usize, self.end as usize) as u32;
+		Some(desc)
+	}
+}
+
+/// Reads a PAK file from a byte buffer with on-the-fly decryption.
+///
+/// Unlike the `MemoryReader` the bytes have no alignment requirements.
+/// This allows reading a PAK file straight out of `include_bytes!`, a network buffer or a memory mapped file without copying it first.
+#[derive(Copy, Clone, Default)]
+pub struct BytesReader<'a> {
+	bytes: &'a [u8],
+	key: Key,
+	directory: &'a [u8],
+	dirnonce: Block,
+	sorted: bool,
+}
+impl<'a> BytesReader<'a> {
+	/// Constructs a new `BytesReader` from the bytes and key.
+	///
+	/// If the bytes are corrupt or the key is not valid an empty directory is returned instead.
+	/// This means that this BytesReader will behave as if it contains no files or directories.
+	pub fn from_bytes(bytes: &'a [u8], key: &Key) -> BytesReader<'a> {
+		// If we don't have enough bytes for a header, just return an empty reader
+		let header1 = match bytes.as_data_view().try_copy::<Header>(0) {
+			Some(header) => header,
+			None => return BytesReader { bytes, ..Default::default() },
+		};
+		// Decrypt the header and extract the root section
+		let header = crypt::decrypt_header(&header1, key);
+		BytesReader::from_info(bytes, key, &header)
+	}
+	/// Constructs a new `BytesReader` from the bytes and key.
+	///
+	/// Unlike `from_bytes` the header is validated and any problems are reported.
+	pub fn try_from_bytes(bytes: &'a [u8], key: &Key) -> Result<BytesReader<'a>> {
+		let header1 = bytes.as_data_view().try_copy::<Header>(0).ok_or(Error::Truncated)?;
+		let header = crypt::decrypt_header(&header1, key);
+		error::check_info(&header, bytes.len() / BLOCK_SIZE)?;
+		Ok(BytesReader::from_info(bytes, key, &header))
+	}
+	/// Constructs a new `BytesReader` from an already decrypted info header.
+	pub(crate) fn from_info(bytes: &'a [u8], key: &Key, info: &InfoHeader) -> BytesReader<'a> {
+		// Figure out the directory and if it's invalid just return an empty one
+		let directory = read_directory(bytes, info);
+		BytesReader { bytes, key: *key, directory, dirnonce: info.directory.nonce, sorted: info.is_sorted() }
+	}
+	/// Returns if this BytesReader contains no files or directories.
+	pub fn is_empty(&self) -> bool {
+		self.directory.is_empty()
+	}
+	fn dir_len(&self) -> usize {
+		self.directory.len() / (Descriptor::BLOCKS_LEN * BLOCK_SIZE)
+	}
+	fn decrypt_desc(&self, index: usize) -> Descriptor {
+		crypt::decrypt_desc_at(self.directory, index, &self.dirnonce, &self.key)
+	}
+	fn section_bytes(&self, section: &Section) -> Option<&'a [u8]> {
+		let range = section.range_usize();
+		self.bytes.get(range.start * BLOCK_SIZE..range.end * BLOCK_SIZE)
+	}
+	/// Finds a descriptor by its path.
+	pub fn find(&self, path: &[u8]) -> Option<Descriptor> {
+		self.find_normalized(&path::normalize(path).ok()?)
+	}
+	fn find_normalized(&self, path: &[u8]) -> Option<Descriptor> {
+		directory::find_by(self.dir_len(), path, self.sorted, directory::Case::Sensitive, |i| self.decrypt_desc(i)).map(|(_, desc)| desc)
+	}
+	/// Finds a descriptor by its path.
+	///
+	/// Returns `InvalidPath` if the path is invalid or `NotFound` if no descriptor exists at the given path.
+	pub fn try_find(&self, path: &[u8]) -> Result<Descriptor> {
+		self.find_normalized(&path::normalize(path)?).ok_or(Error::NotFound)
+	}
+	/// Finds a descriptor by its path starting from the given root directory.
+	pub fn find_sub(&self, root: &Descriptor, path: &[u8]) -> Option<Descriptor> {
+		let range = root.section.range_usize();
+		if range.start > range.end || range.end > self.dir_len() {
+			return None;
+		}
+		let path = path::normalize(path).ok()?;
+		directory::find_by(range.len(), &path, self.sorted, directory::Case::Sensitive, |i| self.decrypt_desc(range.start + i)).map(|(_, desc)| desc)
+	}
+	/// Finds all the descriptors matching the glob pattern.
+	///
+	/// See [`directory::glob`](directory/fn.glob.html) for more information.
+	pub fn glob(&self, pattern: &[u8]) -> Vec<(Vec<u8>, Descriptor)> {
+		directory::glob_by(self.dir_len(), pattern, |i| self.decrypt_desc(i))
+	}
+	/// Returns if the descriptor is a valid file.
+	///
+	/// See [`MemoryReader::is_valid_file`](struct.MemoryReader.html#method.is_valid_file) for more information.
+	pub fn is_valid_file(&self, desc: &Descriptor) -> bool {
+		desc.content_type != 0 &&
+		desc.section.offset >= Header::BLOCKS_LEN as u32 &&
+		self.section_bytes(&desc.section).is_some() &&
+		bytes2blocks(desc.content_size) <= desc.section.size
+	}
+	/// Returns if the descriptor is a valid directory.
+	///
+	/// See [`MemoryReader::is_valid_dir`](struct.MemoryReader.html#method.is_valid_dir) for more information.
+	pub fn is_valid_dir(&self, desc: &Descriptor) -> bool {
+		let range = desc.section.range_usize();
+		desc.content_type == 0 &&
+		desc.section.size == desc.content_size &&
+		range.start <= range.end && range.end <= self.dir_len()
+	}
+	fn check_file(&self, desc: &Descriptor) -> Result<&'a [u8]> {
+		if !desc.is_file() {
+			return Err(Error::NotAFile);
+		}
+		if !self.is_valid_file(desc) {
+			return Err(Error::OutOfRange);
+		}
+		self.section_bytes(&desc.section).ok_or(Error::OutOfRange)
+	}
+	/// Decrypts the contents of the given file descriptor.
+	///
+	/// If given a directory descriptor an empty Vec is returned.
+	/// If the descriptor is corrupt the returned Vec may contain zeroes.
+	pub fn read_data(&self, desc: &Descriptor) -> Vec<u8> {
+		if !desc.is_file() {
+			return Vec::new();
+		}
+		let mut bytes = vec![0; desc.content_size as usize];
+		if let Some(blocks) = self.section_bytes(&desc.section) {
+			crypt::decrypt_data(blocks, &desc.section.nonce, &self.key, 0, &mut bytes);
+		}
+		bytes
+	}
+	/// Decrypts the contents of the given file descriptor.
+	///
+	/// Returns `NotAFile` if given a directory descriptor and `OutOfRange` if the descriptor is corrupt.
+	pub fn try_read_data(&self, desc: &Descriptor) -> Result<Vec<u8>> {
+		let blocks = self.check_file(desc)?;
+		let mut bytes = vec![0; desc.content_size as usize];
+		crypt::decrypt_data(blocks, &desc.section.nonce, &self.key, 0, &mut bytes);
+		Ok(bytes)
+	}
+	/// Decrypts the contents of the given file descriptor into the dest buffer.
+	/// Given a byte offset into the file where to start decrypting.
+	///
+	/// If given a directory descriptor nothing is written to the dest buffer.
+	/// If the descriptor is corrupt nothing may be written to the dest buffer.
+	pub fn read_into(&self, desc: &Descriptor, byte_offset: usize, dest: &mut [u8]) {
+		if !desc.is_file() {
+			return;
+		}
+		if let Some(blocks) = self.section_bytes(&desc.section) {
+			crypt::decrypt_data(blocks, &desc.section.nonce, &self.key, byte_offset, dest);
+		}
+	}
+	/// Decrypts the contents of the given file descriptor into the dest buffer.
+	/// Given a byte offset into the file where to start decrypting.
+	///
+	/// Returns `NotAFile` if given a directory descriptor and `OutOfRange` if the descriptor is corrupt.
+	/// Returns `OutOfRange` if the requested range is not within the file's contents.
+	pub fn try_read_into(&self, desc: &Descriptor, byte_offset: usize, dest: &mut [u8]) -> Result<()> {
+		let blocks = self.check_file(desc)?;
+		if byte_offset.checked_add(dest.len()).is_none_or(|end| end > desc.content_size as usize) {
+			return Err(Error::OutOfRange);
+		}
+		crypt::decrypt_data(blocks, &desc.section.nonce, &self.key, byte_offset, dest);
+		Ok(())
+	}
+	/// Opens the given file descriptor for streaming its decrypted contents.
+	///
+	/// Returns `None` if the descriptor is not a valid file.
+	pub fn open(&self, desc: &Descriptor) -> Option<FileReader<'a>> {
+		if !self.is_valid_file(desc) {
+			return None;
+		}
+		let blocks = self.section_bytes(&desc.section)?;
+		Some(FileReader::new(blocks, &self.key, desc))
+	}
+	/// Opens the given file descriptor for streaming its decrypted contents.
+	///
+	/// Returns `NotAFile` if given a directory descriptor and `OutOfRange` if the descriptor is corrupt.
+	pub fn try_open(&self, desc: &Descriptor) -> Result<FileReader<'a>> {
+		let blocks = self.check_file(desc)?;
+		Ok(FileReader::new(blocks, &self.key, desc))
+	}
+	pub fn iter(&self, desc: &Descriptor) -> BytesReadIter<'a> {
+		BytesReadIter {
+			bytes_reader: *self,
+			start: desc.section.offset,
+			end: u32::min(desc.section.offset.saturating_add(desc.section.size), self.dir_len() as u32),
+		}
+	}
+	/// Iterates over the direct children of the root directory.
+	pub fn iter_root(&self) -> BytesReadIter<'a> {
+		BytesReadIter {
+			bytes_reader: *self,
+			start: 0,
+			end: self.dir_len() as u32,
+		}
+	}
+}
+
+#[test]
+fn test_unaligned_roundtrip() {
+	let key = &[13, 42];
+	let data = b"Hello unaligned world!";
+	let mut edit = MemoryEditor::new();
+	edit.create_file(b"sub/foo", data, key);
+	edit.create_file(b"bar", &[0xCF; 65], key);
+	let (blocks, _) = edit.finish(key);
+
+	// Shift the PAK file by one byte to break its alignment
+	let mut bytes = vec![0; blocks.as_bytes().len() + 1];
+	bytes[1..].copy_from_slice(blocks.as_bytes());
+	let reader = BytesReader::from_bytes(&bytes[1..], key);
+
+	let desc = reader.find(b"sub/foo").unwrap();
+	assert!(reader.is_valid_file(&desc));
+	assert_eq!(reader.read_data(&desc), &data[..]);
+	let mut dest = [0; 9];
+	reader.read_into(&desc, 6, &mut dest);
+	assert_eq!(&dest, b"unaligned");
+
+	let sub = reader.find(b"sub").unwrap();
+	assert!(reader.is_valid_dir(&sub));
+	assert_eq!(reader.find_sub(&sub, b"foo"), Some(desc));
+	assert_eq!(reader.iter(&sub).collect::<Vec<_>>(), [desc]);
+
+	let bar = reader.find(b"bar").unwrap();
+	assert_eq!(reader.read_data(&bar), &[0xCF; 65][..]);
+	let mut content = Vec::new();
+	std::io::Read::read_to_end(&mut reader.open(&bar).unwrap(), &mut content).unwrap();
+	assert_eq!(content, &[0xCF; 65][..]);
+}
@@ -1,4 +1,4 @@
-use std::{mem, slice};
+use std::{convert::TryInto, mem, slice};
 use dataview::Pod;
 use crate::*;
 
@@ -13,6 +13,36 @@ pub fn random(blocks: &mut [Block]) {
 	getrandom::getrandom(dest).unwrap();
 }
 
+/// Random access to encrypted blocks.
+///
+/// Implemented for aligned blocks and for unaligned byte buffers.
+pub trait Blocks {
+	/// Number of whole blocks available.
+	fn blocks_len(&self) -> usize;
+	/// Reads the block at the given index.
+	fn block(&self, i: usize) -> Block;
+}
+impl Blocks for [Block] {
+	#[inline]
+	fn blocks_len(&self) -> usize {
+		self.len()
+	}
+	#[inline]
+	fn block(&self, i: usize) -> Block {
+		self[i]
+	}
+}
+impl Blocks for [u8] {
+	#[inline]
+	fn blocks_len(&self) -> usize {
+		self.len() / BLOCK_SIZE
+	}
+	#[inline]
+	fn block(&self, i: usize) -> Block {
+		self.as_data_view().copy(i * BLOCK_SIZE)
+	}
+}
+
 #[derive(Copy, Clone)]
 #[repr(u8)]
 pub enum Pad {
@@ -51,7 +81,7 @@ fn test_crypt_header_roundtrip() {
 		iv: [1, 999],
 		info: InfoHeader {
 			version: 0x42,
-			unused: [0x13],
+			flags: 0x13,
 			directory: Section {
 				offset: 64,
 				size: 32,
@@ -95,8 +125,31 @@ pub fn decrypt_desc(encrypted_desc: &Descriptor, nonce: &Block, key: &Key) -> De
 	unsafe { mem::transmute(dest) }
 }
 
+/// Decrypts the descriptor at the given index of an encrypted directory.
+pub fn decrypt_desc_at<B: ?Sized + Blocks>(encrypted_dir: &B, index: usize, nonce: &Block, key: &Key) -> Descriptor {
+	let mut dest = <[Block; Descriptor::BLOCKS_LEN]>::default();
+	let offset = index * Descriptor::BLOCKS_LEN;
+	for (j, block) in dest.iter_mut().enumerate() {
+		*block = xor(encrypted_dir.block(offset + j), speck128::encrypt(counter(nonce, offset + j), key));
+	}
+	dest.into()
+}
+
+/// Decrypts the block at the given index.
+pub fn decrypt_block_at<B: ?Sized + Blocks>(blocks: &B, index: usize, nonce: &Block, key: &Key) -> Block {
+	xor(blocks.block(index), speck128::encrypt(counter(nonce, index), key))
+}
+
 pub fn decrypt_dir(blocks: &[Block], dir_section: &Section, key: &Key) -> Vec<Descriptor> {
-	unimplemented!()
+	let dir_offset = dir_section.offset as usize;
+	let dir_size = dir_section.size as usize;
+	let encrypted = match blocks.get(dir_offset..dir_offset + dir_size * Descriptor::BLOCKS_LEN) {
+		Some(encrypted) => encrypted,
+		None => return Vec::new(),
+	};
+	let mut dir = vec![Descriptor::default(); dir_size];
+	decrypt(encrypted, &dir_section.nonce, key, dir.as_data_view_mut().slice_tail_mut(0));
+	dir
 }
 
 pub fn encrypt_dir_inplace(dir: &mut [Descriptor], nonce: &Block, key: &Key) {
@@ -118,13 +171,25 @@ fn test_crypt_desc_roundtrip() {
 	assert_eq!(desc, crypted);
 }
 
+#[test]
+fn test_decrypt_dir() {
+	let dir = [Descriptor::dir(b"sub", 1), Descriptor::file(b"hello world")];
+	let key = [133, 422];
+	let section = Section { offset: 1, size: 2, nonce: [31415, 2781] };
+	let mut blocks = vec![Block::default(); 1 + 2 * Descriptor::BLOCKS_LEN];
+	unsafe { encrypt_dir(&dir, &section.nonce, &key, &mut blocks[1..]); }
+	assert_eq!(decrypt_dir(&blocks, &section, &key), dir);
+	// Out of range directories decrypt as empty
+	assert!(decrypt_dir(&blocks[..8], &section, &key).is_empty());
+}
+
 //----------------------------------------------------------------
 // Data
 
-pub fn decrypt_data(blocks: &[Block], nonce: &Block, key: &Key, mut byte_offset: usize, mut dest: &mut [u8]) {
+pub fn decrypt_data<B: ?Sized + Blocks>(blocks: &B, nonce: &Block, key: &Key, mut byte_offset: usize, mut dest: &mut [u8]) {
 	// Range check to ensure the dest blocks are large enough
 	let byte_end = byte_offset + dest.len();
-	if blocks.as_bytes().get(byte_offset..byte_end).is_none() {
+	if byte_end > blocks.blocks_len() * BLOCK_SIZE {
 		return;
 	}
 	if dest.is_empty() {
@@ -134,20 +199,20 @@ pub fn decrypt_data(blocks: &[Block], nonce: &Block, key: &Key, mut byte_offset:
 	let mut block_start = byte_offset / BLOCK_SIZE;
 	let block_end = byte_end / BLOCK_SIZE;
 	let block_offset = byte_offset - block_start * BLOCK_SIZE;
-	unsafe_assume!(block_start < blocks.len());
-	unsafe_assume!(block_end <= blocks.len());
+	unsafe_assume!(block_start < blocks.blocks_len());
+	unsafe_assume!(block_end <= blocks.blocks_len());
 	unsafe_assume!(block_offset < BLOCK_SIZE);
 	// If they're the same then we're decrypting a subsection of a single block
 	if block_start == block_end {
 		unsafe_assume!(dest.len() <= BLOCK_SIZE - block_offset);
-		decrypt_subdata(&blocks[block_start], counter(nonce, block_start), key, block_offset, dest);
+		decrypt_subdata(&blocks.block(block_start), counter(nonce, block_start), key, block_offset, dest);
 		return;
 	}
 	// Spans at least two blocks
 	unsafe_assume!(dest.len() >= BLOCK_SIZE - block_offset);
 	// Decrypt the prefix given byte offset
 	if block_offset != 0 {
-		decrypt_subdata(&blocks[block_start], counter(nonce, block_start), key, block_offset, &mut dest[..BLOCK_SIZE - block_offset]);
+		decrypt_subdata(&blocks.block(block_start), counter(nonce, block_start), key, block_offset, &mut dest[..BLOCK_SIZE - block_offset]);
 		// Adjust the start parameters after the prefix
 		let prefix_size = BLOCK_SIZE - block_offset;
 		dest = &mut dest[prefix_size..];
@@ -158,8 +223,8 @@ pub fn decrypt_data(blocks: &[Block], nonce: &Block, key: &Key, mut byte_offset:
 	debug_assert_eq!(byte_offset % BLOCK_SIZE, 0);
 	// Decrypt the blocks in the middle
 	for block_i in block_start..block_end {
-		unsafe_assume!(block_i < blocks.len());
-		let block = xor(blocks[block_i], speck128::encrypt(counter(nonce, block_i), key));
+		unsafe_assume!(block_i < blocks.blocks_len());
+		let block = xor(blocks.block(block_i), speck128::encrypt(counter(nonce, block_i), key));
 		unsafe_assume!(dest.len() >= BLOCK_SIZE);
 		block.as_data_view().copy_into(0, &mut dest[..BLOCK_SIZE]);
 		dest = &mut dest[BLOCK_SIZE..];
@@ -167,9 +232,9 @@ pub fn decrypt_data(blocks: &[Block], nonce: &Block, key: &Key, mut byte_offset:
 	}
 	// Decrypt the tail block
 	if dest.len() != 0 {
-		unsafe_assume!(block_end < blocks.len());
+		unsafe_assume!(block_end < blocks.blocks_len());
 		unsafe_assume!(dest.len() < BLOCK_SIZE);
-		decrypt_subdata(&blocks[block_end], counter(nonce, block_end), key, 0, dest);
+		decrypt_subdata(&blocks.block(block_end), counter(nonce, block_end), key, 0, dest);
 	}
 }
 fn decrypt_subdata(block_ref: &Block, nonce: Block, key: &Key, byte_offset: usize, dest: &mut [u8]) {
@@ -253,6 +318,38 @@ pub fn reencrypt_data(blocks: &mut [Block], old_nonce: &Block, new_nonce: &Block
 	}
 }
 
+/// Hashes the bytes with Speck128 in a Davies-Meyer construction.
+///
+/// The bytes are padded with `0x80` and zeroes followed by a block holding the length in bytes.
+pub fn hash(bytes: &[u8]) -> Block {
+	let mut state: Block = [0x6a09e667f3bcc908, 0xbb67ae8584caa73b];
+	let mut compress = |chunk: &[u8]| {
+		let mut buf = [0u8; BLOCK_SIZE];
+		buf[..chunk.len()].copy_from_slice(chunk);
+		if chunk.len() < BLOCK_SIZE {
+			buf[chunk.len()] = 0x80;
+		}
+		let message = [u64::from_le_bytes(buf[..8].try_into().unwrap()), u64::from_le_bytes(buf[8..].try_into().unwrap())];
+		state = xor(speck128::encrypt(state, &message), state);
+	};
+	let mut chunks = bytes.chunks_exact(BLOCK_SIZE);
+	for chunk in &mut chunks {
+		compress(chunk);
+	}
+	compress(chunks.remainder());
+	let len = bytes.len() as u64;
+	state = xor(speck128::encrypt(state, &[len, 0]), state);
+	state
+}
+
+/// Keyed hash of the bytes.
+///
+/// Encrypts the hash of the bytes with a key derived from the given key, only the owner of the key can produce it.
+pub fn mac(bytes: &[u8], key: &Key) -> Block {
+	let mac_key = speck128::encrypt([u64::from_le_bytes(*b"PAK MAC "), 0], key);
+	speck128::encrypt(hash(bytes), &mac_key)
+}
+
 #[test]
 fn test_crypt_subdata() {
 	let mut src = [0; 15];
@@ -263,7 +360,7 @@ fn test_crypt_subdata() {
 		let mut blocks = [[0u64; 2]; 1];
 		encrypt_data(&mut blocks, nonce, key, i % 8, &src[i..], Pad::Zero);
 		let mut dest = [0; 15];
-		decrypt_data(&blocks, nonce, key, i % 8, &mut dest[i..]);
+		decrypt_data(&blocks[..], nonce, key, i % 8, &mut dest[i..]);
 		assert_eq!(&src[i..], &dest[i..]);
 	}
 }
@@ -277,7 +374,31 @@ fn test_crypt_data() {
 		let mut blocks = [[0u64; 2]; 4];
 		encrypt_data(&mut blocks, nonce, key, i, &src, Pad::Zero);
 		let mut dest = [0; 31];
-		decrypt_data(&blocks, nonce, key, i, &mut dest);
+		decrypt_data(&blocks[..], nonce, key, i, &mut dest);
 		assert_eq!(&src, &dest);
 	}
 }
+#[test]
+fn test_crypt_data_unaligned() {
+	let mut src = [0; 31];
+	getrandom::getrandom(&mut src).unwrap();
+	let key = &[13, 42];
+	let nonce = &[0x13, 0x42];
+	let mut blocks = [[0u64; 2]; 3];
+	encrypt_data(&mut blocks, nonce, key, 5, &src, Pad::Zero);
+	// Shift the encrypted bytes by one to break their alignment
+	let mut bytes = vec![0; BLOCK_SIZE * 3 + 1];
+	bytes[1..].copy_from_slice(blocks.as_bytes());
+	for i in 0..16 {
+		let mut dest = [0; 31];
+		decrypt_data(&bytes[1..], nonce, key, 5 + i, &mut dest[i..]);
+		assert_eq!(&src[i..], &dest[i..]);
+	}
+}
+#[test]
+fn test_hash() {
+	assert_ne!(hash(b""), hash(b"\x80"));
+	assert_ne!(hash(b"0123456789abcdef"), hash(b"0123456789abcdef\x80"));
+	assert_eq!(hash(b"content"), hash(b"content"));
+	assert_ne!(hash(b"content"), hash(b"contenT"));
+}
@@ -0,0 +1,174 @@
+/*!
+Structural differences between PAK files.
+
+Compares the files of two PAK files by path and content hash, see [`diff`](fn.diff.html).
+*/
+
+use std::fmt;
+use std::collections::{HashMap, HashSet};
+use crate::*;
+
+/// A change to a file between two PAK files.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Change {
+	/// The file exists only in the new PAK file.
+	Added { path: Vec<u8>, desc: Descriptor },
+	/// The file exists only in the old PAK file.
+	Removed { path: Vec<u8>, desc: Descriptor },
+	/// The file moved to a new path with the same contents.
+	Moved { from: Vec<u8>, to: Vec<u8>, desc: Descriptor },
+	/// The file's contents changed.
+	Modified { path: Vec<u8>, old: Descriptor, new: Descriptor },
+	/// The file's content type changed, follows `Modified` or `Moved` if its contents also changed or it moved.
+	ContentType { path: Vec<u8>, old: u32, new: u32 },
+}
+impl fmt::Display for Change {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let s = |path: &[u8]| String::from_utf8_lossy(path).into_owned();
+		match self {
+			Change::Added { path, desc } => write!(f, "A {} ({} bytes)", s(path), desc.content_size),
+			Change::Removed { path, .. } => write!(f, "D {}", s(path)),
+			Change::Moved { from, to, .. } => write!(f, "R {} -> {}", s(from), s(to)),
+			Change::Modified { path, old, new } => write!(f, "M {} ({} -> {} bytes)", s(path), old.content_size, new.content_size),
+			Change::ContentType { path, old, new } => write!(f, "T {} (type {} -> {})", s(path), old, new),
+		}
+	}
+}
+
+struct File {
+	path: Vec<u8>,
+	desc: Descriptor,
+	hash: Block,
+}
+fn files(reader: &MemoryReader) -> Result<Vec<File>> {
+	let mut files = Vec::new();
+	for (path, desc) in reader.walk() {
+		if desc.is_file() {
+			let hash = crypt::hash(&reader.try_read_data(&desc)?);
+			files.push(File { path, desc, hash });
+		}
+	}
+	Ok(files)
+}
+
+/// Compares the files of two PAK files.
+///
+/// Files are matched by path and compared by the hash of their decrypted contents and their content type.
+/// A file removed from the old PAK file and added in the new PAK file with the same contents is reported as moved.
+/// Directories are not reported, only the files they contain.
+///
+/// Returns the changes in directory order of the new PAK file followed by the removed files in directory order of the old PAK file.
+/// Returns `OutOfRange` if any file in either PAK file is not valid.
+///
+/// # Examples
+///
+/// ```
+/// use pak::diff::{diff, Change};
+///
+/// let key = &[13, 42];
+/// let mut edit = pak::MemoryEditor::new();
+/// edit.create_file(b"a", b"a", key);
+/// edit.create_file(b"b", b"b", key);
+/// let (old, _) = edit.finish(key);
+///
+/// let mut edit = pak::MemoryEditor::new();
+/// edit.create_file(b"a", b"changed", key);
+/// edit.create_file(b"dir/b", b"b", key);
+/// let (new, _) = edit.finish(key);
+///
+/// let changes = diff(&pak::MemoryReader::from_blocks(&old, key), &pak::MemoryReader::from_blocks(&new, key)).unwrap();
+/// let changes: Vec<String> = changes.iter().map(|change| change.to_string()).collect();
+/// assert_eq!(changes, ["M a (1 -> 7 bytes)", "R b -> dir/b"]);
+/// ```
+pub fn diff(old: &MemoryReader, new: &MemoryReader) -> Result<Vec<Change>> {
+	let old_files = files(old)?;
+	let new_files = files(new)?;
+	let old_paths: HashMap<&[u8], &File> = old_files.iter().map(|file| (&file.path[..], file)).collect();
+	let new_paths: HashMap<&[u8], &File> = new_files.iter().map(|file| (&file.path[..], file)).collect();
+
+	// Removed files by content hash, candidates for moved files
+	let mut removed: HashMap<Block, Vec<&File>> = HashMap::new();
+	for file in old_files.iter().rev().filter(|file| !new_paths.contains_key(&file.path[..])) {
+		removed.entry(file.hash).or_default().push(file);
+	}
+
+	let mut changes = Vec::new();
+	let mut moved = HashSet::new();
+	for file in &new_files {
+		let old = match old_paths.get(&file.path[..]) {
+			Some(old) => {
+				if old.hash != file.hash {
+					changes.push(Change::Modified { path: file.path.clone(), old: old.desc, new: file.desc });
+				}
+				old
+			},
+			None => match removed.get_mut(&file.hash).and_then(Vec::pop) {
+				Some(old) => {
+					changes.push(Change::Moved { from: old.path.clone(), to: file.path.clone(), desc: file.desc });
+					moved.insert(&old.path[..]);
+					old
+				},
+				None => {
+					changes.push(Change::Added { path: file.path.clone(), desc: file.desc });
+					continue;
+				},
+			},
+		};
+		if old.desc.content_type != file.desc.content_type {
+			changes.push(Change::ContentType { path: file.path.clone(), old: old.desc.content_type, new: file.desc.content_type });
+		}
+	}
+
+	// Removed files which were not moved
+	for file in &old_files {
+		if !new_paths.contains_key(&file.path[..]) && !moved.contains(&file.path[..]) {
+			changes.push(Change::Removed { path: file.path.clone(), desc: file.desc });
+		}
+	}
+	Ok(changes)
+}
+
+#[test]
+fn test_diff() {
+	let key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	edit.create_file(b"same", b"same", key);
+	edit.create_file(b"modified", b"old", key);
+	edit.create_file(b"removed", b"removed", key);
+	edit.create_file(b"dir/moved", b"moved", key);
+	edit.create_file(b"retyped", b"retyped", key);
+	edit.create_file(b"both", b"old", key);
+	edit.create_file(b"copy1", b"copy", key);
+	edit.create_file(b"copy2", b"copy", key);
+	let (old, _) = edit.finish(key);
+
+	let new_key = &[1, 2];
+	let mut edit = MemoryEditor::new();
+	edit.create_file(b"same", b"same", new_key);
+	edit.create_file(b"modified", b"new", new_key);
+	edit.create_file(b"other/moved", b"moved", new_key);
+	edit.edit_file(b"retyped").set_content(7, 7).allocate_data().init_data(b"retyped", new_key);
+	edit.edit_file(b"both").set_content(7, 4).allocate_data().init_data(b"both", new_key);
+	edit.create_file(b"\xffinvalid", b"invalid", new_key);
+	edit.create_file(b"added", b"added", new_key);
+	edit.create_file(b"copy3", b"copy", new_key);
+	let (new, _) = edit.finish(new_key);
+
+	let changes = diff(&MemoryReader::from_blocks(&old, key), &MemoryReader::from_blocks(&new, new_key)).unwrap();
+	let changes: Vec<String> = changes.iter().map(|change| change.to_string()).collect();
+	assert_eq!(changes, [
+		"M modified (3 -> 3 bytes)",
+		"R dir/moved -> other/moved",
+		"T retyped (type 1 -> 7)",
+		"M both (3 -> 4 bytes)",
+		"T both (type 1 -> 7)",
+		"A \u{fffd}invalid (7 bytes)",
+		"A added (5 bytes)",
+		"R copy1 -> copy3",
+		"D removed",
+		"D copy2",
+	]);
+
+	let reader = MemoryReader::from_blocks(&new, new_key);
+	assert!(diff(&reader, &reader).unwrap().is_empty());
+}
@@ -10,7 +10,7 @@ There are two types, directories and files, which share the same [descriptor str
 * File descriptors have their `content_type` non-zero (the interpretation of the value is left to the user) and the `content_size` specifies the size of the file in bytes.
 */
 
-use std::{cmp, fmt, str};
+use std::{cmp, convert, fmt, result, str};
 use crate::*;
 
 /// Compares if the next component of the path matches the file descriptor.
@@ -58,6 +58,96 @@ pub fn name_eq<'a>(desc: &Descriptor, path: &'a [u8]) -> Option<&'a [u8]> {
 	}
 }
 
+/// Case sensitivity when comparing names.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Case {
+	/// Names are compared byte for byte.
+	#[default]
+	Sensitive,
+	/// ASCII letters are compared case-insensitively, other bytes are compared exactly.
+	Ascii,
+	/// Names are compared after simple case folding every character, the common and simple mappings of the Unicode `CaseFolding.txt`.
+	///
+	/// Unlike full case folding a character always folds to a single character, eg. `ß` does not match `ss`.
+	/// Names which are not valid UTF-8 are compared as with `Case::Ascii`.
+	Unicode,
+}
+impl Case {
+	/// Compares the names with this case sensitivity.
+	pub fn names_eq(self, a: &[u8], b: &[u8]) -> bool {
+		match self {
+			Case::Sensitive => a == b,
+			Case::Ascii => a.eq_ignore_ascii_case(b),
+			Case::Unicode => match (str::from_utf8(a), str::from_utf8(b)) {
+				(Ok(a), Ok(b)) => a.chars().map(simple_fold).eq(b.chars().map(simple_fold)),
+				_ => a.eq_ignore_ascii_case(b),
+			},
+		}
+	}
+}
+
+// Simple case folding of a character
+fn simple_fold(chr: char) -> char {
+	// Characters whose simple case folding differs from their lowercase
+	match chr {
+		'\u{0345}' | '\u{1fbe}' => return 'ι',
+		'ς' => return 'σ',
+		'ſ' => return 's',
+		'ϐ' => return 'β',
+		'ϑ' => return 'θ',
+		'ϕ' => return 'φ',
+		'ϖ' => return 'π',
+		'ϰ' => return 'κ',
+		'ϱ' => return 'ρ',
+		'ϵ' => return 'ε',
+		'ẛ' => return 'ṡ',
+		'\u{1c80}' => return 'в',
+		'\u{1c81}' => return 'д',
+		'\u{1c82}' => return 'о',
+		'\u{1c83}' => return 'с',
+		'\u{1c84}' | '\u{1c85}' => return 'т',
+		'\u{1c86}' => return 'ъ',
+		'\u{1c87}' => return 'ѣ',
+		'\u{1c88}' => return 'ꙋ',
+		_ => (),
+	}
+	// Characters lowercasing to multiple characters (only `İ`) have no simple case folding
+	let mut lower = chr.to_lowercase();
+	match (lower.next(), lower.next()) {
+		(Some(lower), None) => lower,
+		_ => chr,
+	}
+}
+
+/// Compares if the next component of the path matches the file descriptor with the given case sensitivity.
+///
+/// See [`name_eq`](fn.name_eq.html) for more information.
+///
+/// # Examples
+///
+/// ```
+/// use pak::Descriptor;
+/// use pak::directory::{name_eq_case, Case};
+///
+/// let desc = Descriptor::file("Ärger.txt".as_bytes());
+///
+/// assert_eq!(name_eq_case(&desc, "Ärger.TXT/a".as_bytes(), Case::Ascii), Some(&b"a"[..]));
+/// assert_eq!(name_eq_case(&desc, "ärger.txt".as_bytes(), Case::Ascii), None);
+/// assert_eq!(name_eq_case(&desc, "ÄRGER.TXT".as_bytes(), Case::Unicode), Some(&b""[..]));
+///
+/// // Simple case folding matches final sigma and long s
+/// assert!(Case::Unicode.names_eq("ΟΔΟΣ".as_bytes(), "οδος".as_bytes()));
+/// assert!(Case::Unicode.names_eq("ſtraße".as_bytes(), "STRAẞE".as_bytes()));
+/// assert_eq!(name_eq_case(&desc, "Ärger.txt".as_bytes(), Case::Sensitive), Some(&b""[..]));
+/// ```
+pub fn name_eq_case<'a>(desc: &Descriptor, path: &'a [u8], case: Case) -> Option<&'a [u8]> {
+	if case == Case::Sensitive {
+		return name_eq(desc, path);
+	}
+	let (component, tail) = split_component(path);
+	if case.names_eq(desc.name(), component) { Some(tail) } else { None }
+}
+
 /// Calculates the next sibling index for the given descriptor.
 ///
 /// When iterating over a directory, calculate the next sibling index for the given descriptor.
@@ -154,7 +244,14 @@ pub fn find_dir<'a>(dir: &'a [Descriptor], path: &[u8]) -> Option<&'a [Descripto
 /// Returns a slice with length larger than or equal to one if a directory descriptor was found at the given path.
 /// The first entry in the slice is the directory descriptor, the tail are the child descriptors contained within the directory.
 /// These children also contain any subdirectories of the returned directory.
-pub fn find<'a>(dir: &'a [Descriptor], mut path: &[u8]) -> &'a [Descriptor] {
+pub fn find<'a>(dir: &'a [Descriptor], path: &[u8]) -> &'a [Descriptor] {
+	find_case(dir, path, Case::Sensitive)
+}
+
+/// Traverse the directory with the given path and case sensitivity.
+///
+/// See [`find`](fn.find.html) for more information.
+pub fn find_case<'a>(dir: &'a [Descriptor], mut path: &[u8], case: Case) -> &'a [Descriptor] {
 	// Reject empty paths
 	if path.len() == 0 {
 		return &dir[..0];
@@ -164,7 +261,7 @@ pub fn find<'a>(dir: &'a [Descriptor], mut path: &[u8]) -> &'a [Descriptor] {
 	while i < end {
 		let desc = &dir[i];
 		let next_i = next_sibling(desc, i, end);
-		if let Some(tail) = name_eq(desc, path) {
+		if let Some(tail) = name_eq_case(desc, path, case) {
 			// Exactly matching descriptor found
 			if tail.len() == 0 {
 				return &dir[i..next_i];
@@ -189,26 +286,69 @@ pub fn find<'a>(dir: &'a [Descriptor], mut path: &[u8]) -> &'a [Descriptor] {
 /// Finds a descriptor with the given name in an encrypted directory.
 ///
 /// The directory stays encrypted and only decrypts a single descriptor at the time.
-pub fn find_encrypted(encrypted_dir: &[Descriptor], mut path: &[u8], nonce: &Block, key: &Key) -> Option<Descriptor> {
+pub fn find_encrypted(encrypted_dir: &[Descriptor], path: &[u8], nonce: &Block, key: &Key) -> Option<Descriptor> {
+	find_encrypted_case(encrypted_dir, path, nonce, key, Case::Sensitive)
+}
+
+/// Finds a descriptor with the given name and case sensitivity in an encrypted directory.
+///
+/// See [`find_encrypted`](fn.find_encrypted.html) for more information.
+pub fn find_encrypted_case(encrypted_dir: &[Descriptor], path: &[u8], nonce: &Block, key: &Key, case: Case) -> Option<Descriptor> {
+	let blocks: &[Block] = encrypted_dir.as_data_view().slice_tail(0);
+	find_by(encrypted_dir.len(), path, false, case, |i| crypt::decrypt_desc_at(blocks, i, nonce, key)).map(|(_, desc)| desc)
+}
+
+/// Traverses a directory of `len` descriptors where the descriptors are produced on demand.
+///
+/// If the directory is sorted and the case sensitive, see [`sort`](fn.sort.html), the directories of a level are walked and its files are binary searched.
+/// Returns the index and the descriptor found at the given path.
+pub(crate) fn find_by<F: FnMut(usize) -> Descriptor>(len: usize, path: &[u8], sorted: bool, case: Case, mut get: F) -> Option<(usize, Descriptor)> {
+	match try_find_by(len, path, sorted, case, |i| Ok::<_, convert::Infallible>(get(i))) {
+		Ok(found) => found,
+		Err(never) => match never {},
+	}
+}
+
+/// Traverses a directory of `len` descriptors where producing the descriptors may fail, see [`find_by`](fn.find_by.html).
+///
+/// Returns the first error produced by `get`. The `AsyncReader` mirrors this traversal with an async `get`.
+pub(crate) fn try_find_by<E, F: FnMut(usize) -> result::Result<Descriptor, E>>(len: usize, mut path: &[u8], sorted: bool, case: Case, mut get: F) -> result::Result<Option<(usize, Descriptor)>, E> {
 	// Reject empty paths
 	if path.len() == 0 {
-		return None;
+		return Ok(None);
 	}
+	let binary = sorted && case == Case::Sensitive;
 	let mut i = 0;
-	let mut end = encrypted_dir.len();
-	let mut nonce = *nonce;
+	let mut end = len;
 	while i < end {
-		let desc = crypt::decrypt_desc(&encrypted_dir[i], &nonce, key);
+		let desc = get(i)?;
+		// The remaining siblings are files sorted by name
+		if binary && !desc.is_dir() {
+			let name = match sorted_file_name(path) {
+				Some(name) => name,
+				None => return Ok(None),
+			};
+			let (mut lo, mut hi) = (i, end);
+			while lo < hi {
+				let mid = lo + (hi - lo) / 2;
+				let desc = get(mid)?;
+				match desc.name().cmp(name) {
+					cmp::Ordering::Less => lo = mid + 1,
+					cmp::Ordering::Greater => hi = mid,
+					cmp::Ordering::Equal => return Ok(Some((mid, desc))),
+				}
+			}
+			return Ok(None);
+		}
 		let next_i = next_sibling(&desc, i, end);
-		if let Some(tail) = name_eq(&desc, path) {
+		if let Some(tail) = name_eq_case(&desc, path, case) {
 			// Exactly matching descriptor found
 			if tail.len() == 0 {
-				return Some(desc);
+				return Ok(Some((i, desc)));
 			}
 			// Continue traversing directory descriptor
 			if desc.is_dir() {
 				path = tail;
-				nonce = crypt::counter(&nonce, Descriptor::BLOCKS_LEN);
 				i = i + 1;
 				end = next_i;
 				continue;
@@ -216,12 +356,326 @@ pub fn find_encrypted(encrypted_dir: &[Descriptor], mut path: &[u8], nonce: &Blo
 			// Found a file descriptor when expecting a director descriptor
 			// Continue, maybe a directory descriptor exists with the same name
 		}
+		else if binary && name_cmp(&desc, path) == cmp::Ordering::Greater && sorted_file_name(path).is_none() {
+			// All the remaining directories are greater and files cannot contain the rest of the path
+			break;
+		}
 		// Advance the iteration
-		nonce = crypt::counter(&nonce, (next_i - i) * Descriptor::BLOCKS_LEN);
 		i = next_i;
 	}
 	// No descriptor with this path found
-	return None;
+	return Ok(None);
+}
+
+type WalkFilter<'a> = Box<dyn FnMut(&[u8], &Descriptor) -> bool + 'a>;
+
+/// Depth-first directory walker.
+///
+/// Yields every descriptor together with its full path, its components joined by `/`.
+/// Created by [`walk`](fn.walk.html) and [`walk_encrypted`](fn.walk_encrypted.html).
+pub struct Walk<'a> {
+	dir: &'a [Descriptor],
+	crypt: Option<(Block, Key)>,
+	// The end index and path length of every directory being walked
+	stack: Vec<(usize, usize)>,
+	i: usize,
+	path: Vec<u8>,
+	max_depth: usize,
+	filter: Option<WalkFilter<'a>>,
+}
+impl<'a> Walk<'a> {
+	/// Limits the depth of the walk.
+	///
+	/// A depth of `1` only yields the direct children of the root.
+	pub fn max_depth(mut self, depth: usize) -> Walk<'a> {
+		self.max_depth = depth;
+		self
+	}
+	/// Filters the descriptors by their path, pruning rejected directories.
+	///
+	/// Descriptors for which the filter returns `false` are skipped.
+	/// When a directory is rejected its entire subtree is skipped, unlike `Iterator::filter`.
+	pub fn filter_entry<F: FnMut(&[u8], &Descriptor) -> bool + 'a>(mut self, filter: F) -> Walk<'a> {
+		self.filter = Some(Box::new(filter));
+		self
+	}
+	fn get(&self, i: usize) -> Descriptor {
+		match &self.crypt {
+			Some((nonce, key)) => crypt::decrypt_desc_at(self.dir.as_data_view().slice_tail::<Block>(0), i, nonce, key),
+			None => self.dir[i],
+		}
+	}
+}
+impl<'a> Iterator for Walk<'a> {
+	type Item = (Vec<u8>, Descriptor);
+	fn next(&mut self) -> Option<(Vec<u8>, Descriptor)> {
+		loop {
+			// Close the directories which have been walked completely
+			let (end, path_len) = loop {
+				let &(end, path_len) = self.stack.last()?;
+				if self.i < end {
+					break (end, path_len);
+				}
+				self.stack.pop();
+			};
+			let depth = self.stack.len();
+			let desc = self.get(self.i);
+			let next_i = next_sibling(&desc, self.i, end);
+			// Build the path of this descriptor
+			self.path.truncate(path_len);
+			if path_len != 0 {
+				self.path.push(b'/');
+			}
+			self.path.extend_from_slice(desc.name());
+			// Skip the descriptor and its children if rejected by the filter
+			if let Some(filter) = &mut self.filter {
+				if !filter(&self.path, &desc) {
+					self.i = next_i;
+					continue;
+				}
+			}
+			// Descend into directories unless the maximum depth is reached
+			if desc.is_dir() && depth < self.max_depth {
+				self.stack.push((next_i, self.path.len()));
+				self.i += 1;
+			}
+			else {
+				self.i = next_i;
+			}
+			return Some((self.path.clone(), desc));
+		}
+	}
+}
+
+/// Walks the directory depth-first.
+///
+/// # Examples
+///
+/// ```
+/// use pak::Descriptor;
+/// use pak::directory::walk;
+///
+/// let dir = [
+///     Descriptor::dir(b"Foo", 2),
+///     Descriptor::file(b"Bar"),
+///     Descriptor::file(b"Baz"),
+///     Descriptor::dir(b"Sub", 1),
+///     Descriptor::dir(b"Dir", 0),
+///     Descriptor::file(b"File"),
+/// ];
+///
+/// let paths: Vec<_> = walk(&dir).map(|(path, _)| path).collect();
+/// assert_eq!(paths, [&b"Foo"[..], b"Foo/Bar", b"Foo/Baz", b"Sub", b"Sub/Dir", b"File"]);
+///
+/// let files: Vec<_> = walk(&dir).filter(|(_, desc)| desc.is_file()).map(|(path, _)| path).collect();
+/// assert_eq!(files, [&b"Foo/Bar"[..], b"Foo/Baz", b"File"]);
+///
+/// let pruned: Vec<_> = walk(&dir).filter_entry(|path, _| path != b"Foo").map(|(path, _)| path).collect();
+/// assert_eq!(pruned, [&b"Sub"[..], b"Sub/Dir", b"File"]);
+///
+/// let top: Vec<_> = walk(&dir).max_depth(1).map(|(path, _)| path).collect();
+/// assert_eq!(top, [&b"Foo"[..], b"Sub", b"File"]);
+/// ```
+pub fn walk(dir: &[Descriptor]) -> Walk<'_> {
+	Walk {
+		dir,
+		crypt: None,
+		stack: vec![(dir.len(), 0)],
+		i: 0,
+		path: Vec::new(),
+		max_depth: usize::MAX,
+		filter: None,
+	}
+}
+
+/// Walks an encrypted directory depth-first.
+///
+/// The directory stays encrypted and only decrypts a single descriptor at the time.
+pub fn walk_encrypted<'a>(encrypted_dir: &'a [Descriptor], nonce: &Block, key: &Key) -> Walk<'a> {
+	Walk {
+		crypt: Some((*nonce, *key)),
+		..walk(encrypted_dir)
+	}
+}
+
+/// Compares the name of the descriptor with the next component of the path.
+///
+/// This is the order of the siblings in a sorted directory, see [`sort`](fn.sort.html).
+pub fn name_cmp(desc: &Descriptor, path: &[u8]) -> cmp::Ordering {
+	desc.name().cmp(split_component(path).0)
+}
+
+fn split_component(path: &[u8]) -> (&[u8], &[u8]) {
+	match path.iter().position(|&chr| chr == b'/' || chr == b'\\') {
+		Some(k) => (&path[..k], &path[k + 1..]),
+		None => (path, &path[path.len()..]),
+	}
+}
+
+fn wildcard_eq(pattern: &[u8], name: &[u8]) -> bool {
+	let (mut p, mut n) = (0, 0);
+	// Position of the last `*` and the name position it is matched against
+	let mut star = None;
+	while n < name.len() {
+		if p < pattern.len() && (pattern[p] == b'?' || pattern[p] == name[n]) {
+			p += 1;
+			n += 1;
+		}
+		else if p < pattern.len() && pattern[p] == b'*' {
+			star = Some((p, n));
+			p += 1;
+		}
+		else if let Some((star_p, star_n)) = star {
+			// Backtrack and let the last `*` consume one more byte
+			p = star_p + 1;
+			n = star_n + 1;
+			star = Some((star_p, star_n + 1));
+		}
+		else {
+			return false;
+		}
+	}
+	pattern[p..].iter().all(|&chr| chr == b'*')
+}
+
+/// Compares if the next component of the glob pattern matches the file descriptor.
+///
+/// The component may contain wildcards: `?` matches any single byte and `*` matches any number of bytes.
+/// Components without wildcards are compared with [`name_eq`](fn.name_eq.html).
+///
+/// Returns None if the pattern does not match, otherwise returns the pattern with the matched component removed.
+///
+/// # Examples
+///
+/// ```
+/// use pak::Descriptor;
+/// use pak::directory::glob_eq;
+///
+/// let desc = Descriptor::file(b"texture.png");
+///
+/// assert_eq!(glob_eq(&desc, b"*.png"), Some(&b""[..]));
+/// assert_eq!(glob_eq(&desc, b"tex*/a/b"), Some(&b"a/b"[..]));
+/// assert_eq!(glob_eq(&desc, b"texture.pn?"), Some(&b""[..]));
+/// assert_eq!(glob_eq(&desc, b"*.jpg"), None);
+/// ```
+pub fn glob_eq<'a>(desc: &Descriptor, pattern: &'a [u8]) -> Option<&'a [u8]> {
+	let (component, tail) = split_component(pattern);
+	if !component.iter().any(|&chr| chr == b'*' || chr == b'?') {
+		return name_eq(desc, pattern);
+	}
+	if wildcard_eq(component, desc.name()) { Some(tail) } else { None }
+}
+
+/// Finds all the descriptors matching the glob pattern.
+///
+/// Besides the wildcards supported by [`glob_eq`](fn.glob_eq.html), a `**` component matches any number of nested directories.
+/// Directories whose name cannot match the pattern are skipped entirely.
+///
+/// Returns the full path and descriptor of every match once, in directory order.
+///
+/// # Examples
+///
+/// ```
+/// use pak::Descriptor;
+/// use pak::directory::glob;
+///
+/// let dir = [
+///     Descriptor::dir(b"textures", 3),
+///     Descriptor::file(b"grass.png"),
+///     Descriptor::dir(b"ui", 1),
+///     Descriptor::file(b"button.png"),
+///     Descriptor::file(b"readme.txt"),
+/// ];
+///
+/// let paths: Vec<_> = glob(&dir, b"textures/**/*.png").into_iter().map(|(path, _)| path).collect();
+/// assert_eq!(paths, [&b"textures/grass.png"[..], b"textures/ui/button.png"]);
+/// ```
+pub fn glob(dir: &[Descriptor], pattern: &[u8]) -> Vec<(Vec<u8>, Descriptor)> {
+	glob_by(dir.len(), pattern, |i| dir[i])
+}
+
+/// Finds all the descriptors matching the glob pattern in an encrypted directory.
+///
+/// The directory stays encrypted and only decrypts a single descriptor at the time.
+pub fn glob_encrypted(encrypted_dir: &[Descriptor], pattern: &[u8], nonce: &Block, key: &Key) -> Vec<(Vec<u8>, Descriptor)> {
+	let blocks: &[Block] = encrypted_dir.as_data_view().slice_tail(0);
+	glob_by(encrypted_dir.len(), pattern, |i| crypt::decrypt_desc_at(blocks, i, nonce, key))
+}
+
+/// Finds all the descriptors matching the glob pattern where the descriptors are produced on demand.
+pub(crate) fn glob_by<F: FnMut(usize) -> Descriptor>(len: usize, pattern: &[u8], mut get: F) -> Vec<(Vec<u8>, Descriptor)> {
+	let mut results = Vec::new();
+	// Reject empty patterns
+	if !pattern.is_empty() {
+		// A descriptor may be reached through different expansions of `**`
+		let mut matched = vec![false; len];
+		glob_rec(&mut get, 0, len, pattern, 0, &mut Vec::new(), &mut |path, i, desc| {
+			if !matched[i] {
+				matched[i] = true;
+				results.push((i, path.to_vec(), *desc));
+			}
+		});
+		results.sort_by_key(|&(i, _, _)| i);
+	}
+	results.into_iter().map(|(_, path, desc)| (path, desc)).collect()
+}
+fn glob_push(path: &mut Vec<u8>, desc: &Descriptor) -> usize {
+	let len = path.len();
+	if len != 0 {
+		path.push(b'/');
+	}
+	path.extend_from_slice(desc.name());
+	len
+}
+fn glob_rec(get: &mut dyn FnMut(usize) -> Descriptor, mut i: usize, end: usize, pattern: &[u8], depth: u32, path: &mut Vec<u8>, found: &mut dyn FnMut(&[u8], usize, &Descriptor)) {
+	// Max supported nested directories
+	if depth >= 255 {
+		return;
+	}
+	let (component, mut tail) = split_component(pattern);
+	if component == b"**" {
+		// Repeated `**` components match the same as a single one
+		while split_component(tail).0 == b"**" {
+			tail = split_component(tail).1;
+		}
+		// The pattern starting at the last `**` component and its separator
+		let pattern = if tail.is_empty() { component } else { &pattern[pattern.len() - tail.len() - 3..] };
+		// Match zero directories
+		if !tail.is_empty() {
+			glob_rec(get, i, end, tail, depth, path, found);
+		}
+		// Match one or more directories
+		while i < end {
+			let desc = get(i);
+			let next_i = next_sibling(&desc, i, end);
+			let len = glob_push(path, &desc);
+			if tail.is_empty() {
+				found(path, i, &desc);
+			}
+			if desc.is_dir() {
+				glob_rec(get, i + 1, next_i, pattern, depth + 1, path, found);
+			}
+			path.truncate(len);
+			i = next_i;
+		}
+		return;
+	}
+	while i < end {
+		let desc = get(i);
+		let next_i = next_sibling(&desc, i, end);
+		if let Some(tail) = glob_eq(&desc, pattern) {
+			let len = glob_push(path, &desc);
+			if tail.is_empty() {
+				found(path, i, &desc);
+			}
+			else if desc.is_dir() {
+				glob_rec(get, i + 1, next_i, tail, depth + 1, path, found);
+			}
+			path.truncate(len);
+		}
+		// Advance the iteration, skipping the children of directories which don't match
+		i = next_i;
+	}
 }
 
 /// Art used to render the directory.
@@ -400,6 +854,9 @@ pub fn dir_inc(dir: &mut Vec<Descriptor>, path: &mut &[u8], inc: i32) -> usize {
 }
 
 fn flenck(path: &[u8]) -> i32 {
+	if path.is_empty() {
+		return 0;
+	}
 	let mut components = 0;
 	for i in 0..path.len() {
 		if path[i] == b'/' || path[i] == b'\\' {
@@ -412,11 +869,47 @@ fn flenck(path: &[u8]) -> i32 {
 	return components + 1;
 }
 
+/// Finds a descriptor along the path whose name matches a path component only when compared with the given case sensitivity.
+///
+/// Returns the first such descriptor, creating the path would result in names which only differ by case in the same directory.
+pub fn find_case_conflict<'a>(dir: &'a [Descriptor], mut path: &[u8], case: Case) -> Option<&'a Descriptor> {
+	let mut i = 0;
+	let mut end = dir.len();
+	while !path.is_empty() {
+		let (component, tail) = split_component(path);
+		let mut found = None;
+		while i < end {
+			let desc = &dir[i];
+			let next_i = next_sibling(desc, i, end);
+			if desc.name() == component {
+				if desc.is_dir() && found.is_none() {
+					found = Some((i + 1, next_i));
+				}
+			}
+			else if case.names_eq(desc.name(), component) {
+				return Some(desc);
+			}
+			i = next_i;
+		}
+		// Descend into the directory matching exactly, the remaining components will be created
+		(i, end) = found?;
+		path = tail;
+	}
+	None
+}
+
 /// Creates a new descriptor at the appropriate place given the path.
 ///
 /// Non-existing sub directories are created as needed.
 /// If a file exists where a directory is expected, a directory with the same name is created as the file.
+///
+/// The path is used as is, empty components create unnamed descriptors. Untrusted paths should be normalized first, see [`path::normalize`](../path/fn.normalize.html).
 pub fn create<'a>(dir: &'a mut Vec<Descriptor>, path: &[u8]) -> &'a mut Descriptor {
+	let i = create_index(dir, path);
+	&mut dir[i]
+}
+
+fn create_index(dir: &mut Vec<Descriptor>, path: &[u8]) -> usize {
 	// Dry run to find the index where to insert new descriptors
 	let mut tail = path;
 	let i = dir_inc(dir, &mut tail, 0);
@@ -426,7 +919,7 @@ pub fn create<'a>(dir: &'a mut Vec<Descriptor>, path: &[u8]) -> &'a mut Descript
 
 	// Adding a descriptor which already exists
 	if inc == 0 {
-		return &mut dir[i];
+		return i;
 	}
 
 	// Update the parent directories
@@ -435,18 +928,9 @@ pub fn create<'a>(dir: &'a mut Vec<Descriptor>, path: &[u8]) -> &'a mut Descript
 	debug_assert_eq!(i, _check);
 
 	// Move descriptors to make place for the new ones
-	unsafe {
-		let new_len = i + inc;
-		if new_len > dir.capacity() {
-			let additional = new_len - dir.len();
-			dir.reserve(additional);
-		}
-		let old_len = dir.len();
-		dir.set_len(new_len);
-		for j in (i..old_len).rev() {
-			dir[j + inc] = dir[j];
-		}
-	}
+	let old_len = dir.len();
+	dir.resize(old_len + inc, Descriptor::default());
+	dir.copy_within(i..old_len, i + inc);
 
 	// Initialize inserted descriptors
 	for j in 0..inc {
@@ -460,8 +944,8 @@ pub fn create<'a>(dir: &'a mut Vec<Descriptor>, path: &[u8]) -> &'a mut Descript
 		tail = &tail[if k == tail.len() { k } else { k + 1 }..];
 	}
 
-	// Return the requested descriptor
-	return &mut dir[i + inc - 1];
+	// Return the index of the requested descriptor
+	return i + inc - 1;
 }
 
 /// Removes a descriptor at the given path.
@@ -475,6 +959,7 @@ pub fn create<'a>(dir: &'a mut Vec<Descriptor>, path: &[u8]) -> &'a mut Descript
 /// Returns `true` if a directory descriptor is found at the given path.
 /// The descriptor is removed and optionally copied to the deleted output argument.
 /// All the direct children of the removed directory are moved to its parent directory.
+/// Use [`remove_all`](fn.remove_all.html) to remove the directory together with its children.
 pub fn remove(dir: &mut Vec<Descriptor>, path: &[u8], deleted: Option<&mut Descriptor>) -> bool {
 	// Dry run to find the index of the descriptor to remove
 	let mut temp = path;
@@ -500,6 +985,288 @@ pub fn remove(dir: &mut Vec<Descriptor>, path: &[u8], deleted: Option<&mut Descr
 	return true;
 }
 
+/// Removes a descriptor at the given path together with all its children.
+///
+/// Returns the removed descriptors in directory order, the first being the descriptor at the given path.
+/// Returns an empty vector if no descriptor is found at the given path.
+pub fn remove_all(dir: &mut Vec<Descriptor>, path: &[u8]) -> Vec<Descriptor> {
+	// Dry run to find the index of the descriptor to remove
+	let mut tail = path;
+	let i = dir_inc(dir, &mut tail, 0);
+	if !tail.is_empty() || i >= dir.len() {
+		return Vec::new();
+	}
+
+	// Update the parent directories
+	let len = next_sibling(&dir[i], i, dir.len()) - i;
+	tail = path;
+	let _check = dir_inc(dir, &mut tail, -(len as i32));
+	debug_assert_eq!(i, _check);
+
+	dir.drain(i..i + len).collect()
+}
+
+/// Removes all the descriptors matching the glob pattern together with all their children.
+///
+/// See [`glob`](fn.glob.html) for the supported patterns.
+/// Returns the removed descriptors in directory order.
+///
+/// # Examples
+///
+/// ```
+/// let mut dir = Vec::new();
+/// pak::directory::create(&mut dir, b"textures/grass.png");
+/// pak::directory::create(&mut dir, b"textures/ui/button.png");
+/// pak::directory::create(&mut dir, b"readme.txt");
+///
+/// let removed = pak::directory::remove_glob(&mut dir, b"**/*.png");
+/// assert_eq!(removed.len(), 2);
+/// assert_eq!(dir.len(), 3);
+/// assert_eq!(dir[0].content_size, 1);
+/// ```
+pub fn remove_glob(dir: &mut Vec<Descriptor>, pattern: &[u8]) -> Vec<Descriptor> {
+	let mut removed = vec![false; dir.len()];
+	if !pattern.is_empty() {
+		glob_rec(&mut |i| dir[i], 0, dir.len(), pattern, 0, &mut Vec::new(), &mut |_, i, _| removed[i] = true);
+	}
+	// Remove the children of removed directories
+	for i in 0..dir.len() {
+		if removed[i] {
+			let end = next_sibling(&dir[i], i, dir.len());
+			removed[i..end].fill(true);
+		}
+	}
+	// Number of kept descriptors before every index
+	let mut kept = Vec::with_capacity(dir.len() + 1);
+	kept.push(0);
+	for i in 0..dir.len() {
+		kept.push(kept[i] + !removed[i] as usize);
+	}
+	// Update the child count of the kept directories
+	for i in 0..dir.len() {
+		if !removed[i] && dir[i].is_dir() {
+			let end = next_sibling(&dir[i], i, dir.len());
+			dir[i].content_size = (kept[end] - kept[i + 1]) as u32;
+		}
+	}
+	let mut removed_descs = Vec::new();
+	let mut i = 0;
+	dir.retain(|desc| {
+		i += 1;
+		if removed[i - 1] {
+			removed_descs.push(*desc);
+		}
+		!removed[i - 1]
+	});
+	removed_descs
+}
+
+/// Moves the descriptor at the `from` path together with its children to the `to` path.
+///
+/// The descriptor is renamed to the last component of `to` and any missing parent directories are created.
+/// Returns `false` if no descriptor is found at `from`, a descriptor already exists at `to` or `to` is inside `from`.
+/// The directory remains unchanged.
+///
+/// ```
+/// let mut dir = Vec::new();
+/// pak::directory::create(&mut dir, b"a/b/file");
+/// assert!(pak::directory::rename(&mut dir, b"a/b", b"c/d"));
+/// assert!(pak::directory::find_desc(&dir, b"c/d/file").is_some());
+/// assert!(pak::directory::find_desc(&dir, b"a/b").is_none());
+/// ```
+pub fn rename(dir: &mut Vec<Descriptor>, from: &[u8], to: &[u8]) -> bool {
+	// Dry run to find the index of the descriptor to move
+	let mut tail = from;
+	let i = dir_inc(dir, &mut tail, 0);
+	if !tail.is_empty() || i >= dir.len() {
+		return false;
+	}
+
+	// The destination must not exist and cannot be inside the moved subtree
+	let mut tail = to;
+	dir_inc(dir, &mut tail, 0);
+	if tail.is_empty() {
+		return false;
+	}
+	if to.starts_with(from) && matches!(to.get(from.len()), None | Some(b'/') | Some(b'\\')) {
+		return false;
+	}
+
+	// Take the descriptor and its children out of the directory
+	let len = next_sibling(&dir[i], i, dir.len()) - i;
+	let mut tail = from;
+	let _check = dir_inc(dir, &mut tail, -(len as i32));
+	debug_assert_eq!(i, _check);
+	let subtree: Vec<Descriptor> = dir.drain(i..i + len).collect();
+	insert_unchecked(dir, to, subtree);
+	true
+}
+
+/// Inserts a descriptor together with its children at the given path.
+///
+/// The subtree is a descriptor followed by its children as returned by [`find`](fn.find.html) or [`remove_all`](fn.remove_all.html).
+/// The descriptor is renamed to the last component of the path and any missing parent directories are created.
+/// Returns `false` if the subtree is empty or badly nested, or a descriptor already exists at the given path.
+/// The directory remains unchanged.
+pub fn insert(dir: &mut Vec<Descriptor>, path: &[u8], subtree: &[Descriptor]) -> bool {
+	if subtree.is_empty() || next_sibling(&subtree[0], 0, usize::MAX) != subtree.len() || !is_nested(subtree) {
+		return false;
+	}
+	let mut tail = path;
+	dir_inc(dir, &mut tail, 0);
+	if tail.is_empty() {
+		return false;
+	}
+	insert_unchecked(dir, path, subtree.to_vec());
+	true
+}
+fn insert_unchecked(dir: &mut Vec<Descriptor>, path: &[u8], mut subtree: Vec<Descriptor>) {
+	// Create the destination and replace it with the inserted descriptors
+	let j = create_index(dir, path);
+	let mut tail = path;
+	let _check = dir_inc(dir, &mut tail, subtree.len() as i32 - 1);
+	debug_assert_eq!(j, _check);
+	let name = dir[j].name().to_vec();
+	subtree[0].set_name(&name);
+	dir.splice(j..j + 1, subtree);
+}
+
+/// Returns if the children of every directory descriptor fit within their parent directory.
+pub(crate) fn is_nested(dir: &[Descriptor]) -> bool {
+	let mut ends = vec![dir.len()];
+	for (i, desc) in dir.iter().enumerate() {
+		while ends.last() == Some(&i) {
+			ends.pop();
+		}
+		if desc.is_dir() {
+			let end = i + 1 + desc.content_size as usize;
+			if ends.last().is_some_and(|&parent_end| end > parent_end) {
+				return false;
+			}
+			ends.push(end);
+		}
+	}
+	true
+}
+
+/// Returns the name of the file to binary search for in a sorted directory.
+///
+/// Returns `None` if the path has more than one component and cannot be a file.
+pub(crate) fn sorted_file_name(path: &[u8]) -> Option<&[u8]> {
+	let (name, tail) = split_component(path);
+	if tail.is_empty() { Some(name) } else { None }
+}
+
+/// Sorts the siblings of every directory, directories first and files last, each by name.
+///
+/// Directory descriptors are moved together with their children.
+/// Siblings with equal names keep their relative order.
+///
+/// Files have no children so the files of every directory are contiguous and can be binary searched.
+pub fn sort(dir: &mut [Descriptor]) {
+	let mut sorted = Vec::with_capacity(dir.len());
+	sort_rec(dir, &mut sorted);
+	dir.copy_from_slice(&sorted);
+}
+fn sort_rec(dir: &[Descriptor], sorted: &mut Vec<Descriptor>) {
+	let mut siblings = Vec::new();
+	let mut i = 0;
+	while i < dir.len() {
+		let next_i = next_sibling(&dir[i], i, dir.len());
+		siblings.push(i..next_i);
+		i = next_i;
+	}
+	siblings.sort_by(|a, b| (dir[a.start].is_file(), dir[a.start].name()).cmp(&(dir[b.start].is_file(), dir[b.start].name())));
+	for range in siblings {
+		sorted.push(dir[range.start]);
+		sort_rec(&dir[range.start + 1..range.end], sorted);
+	}
+}
+
+/// Finds the parent of every descriptor, `0` for the root and `i + 1` for the directory descriptor at index i.
+pub(crate) fn parents(dir: &[Descriptor]) -> Vec<usize> {
+	let mut parents = Vec::with_capacity(dir.len());
+	let mut stack = vec![(dir.len(), 0)];
+	for (i, desc) in dir.iter().enumerate() {
+		while stack.last().unwrap().0 <= i {
+			stack.pop();
+		}
+		let &(end, parent) = stack.last().unwrap();
+		parents.push(parent);
+		if desc.is_dir() {
+			stack.push((next_sibling(desc, i, end), i + 1));
+		}
+	}
+	parents
+}
+
+/// Index of the children of every directory in a sorted directory.
+///
+/// Allows finding descriptors with a binary search per path component, see [`SiblingIndex::find`](#method.find).
+#[derive(Clone, Debug, Default)]
+pub struct SiblingIndex {
+	// The children of the root are at 0 and the children of the directory descriptor at index i are at i + 1
+	starts: Vec<u32>,
+	children: Vec<u32>,
+}
+impl SiblingIndex {
+	/// Builds the index for the given directory.
+	pub fn new(dir: &[Descriptor]) -> SiblingIndex {
+		let parents = parents(dir);
+		// Group the children by their parent
+		let mut starts = vec![0u32; dir.len() + 2];
+		for &parent in &parents {
+			starts[parent + 1] += 1;
+		}
+		for k in 1..starts.len() {
+			starts[k] += starts[k - 1];
+		}
+		let mut next = starts.clone();
+		let mut children = vec![0u32; dir.len()];
+		for (i, &parent) in parents.iter().enumerate() {
+			children[next[parent] as usize] = i as u32;
+			next[parent] += 1;
+		}
+		// Directories precede files, merge them by name keeping directories first for equal names
+		for k in 0..starts.len() - 1 {
+			children[starts[k] as usize..starts[k + 1] as usize].sort_by(|&a, &b| dir[a as usize].name().cmp(dir[b as usize].name()));
+		}
+		SiblingIndex { starts, children }
+	}
+	/// Finds a descriptor by its path with a binary search per path component.
+	///
+	/// The directory must be sorted, see [`sort`](fn.sort.html), and be the same directory the index was built from.
+	/// The root is `0` to search the whole directory or the `section.offset` of a directory descriptor to search its children.
+	///
+	/// Returns the index of the descriptor found at the given path.
+	pub fn find(&self, dir: &[Descriptor], root: usize, mut path: &[u8]) -> Option<usize> {
+		// Reject empty paths
+		if path.is_empty() || root + 1 >= self.starts.len() {
+			return None;
+		}
+		let mut node = root;
+		'level: loop {
+			let children = &self.children[self.starts[node] as usize..self.starts[node + 1] as usize];
+			let first = children.partition_point(|&i| name_cmp(&dir[i as usize], path) == cmp::Ordering::Less);
+			for &i in &children[first..] {
+				let desc = &dir[i as usize];
+				// The first sibling not equal is greater than the path component
+				let tail = name_eq(desc, path)?;
+				if tail.is_empty() {
+					return Some(i as usize);
+				}
+				if desc.is_dir() {
+					path = tail;
+					node = i as usize + 1;
+					continue 'level;
+				}
+				// Found a file descriptor when expecting a directory, maybe a directory follows with the same name
+			}
+			return None;
+		}
+	}
+}
+
 pub fn update_dir_address(dir: &mut [Descriptor]) {
 	for (i, desc) in dir.iter_mut().enumerate() {
 		if desc.is_dir() {
@@ -509,6 +1276,57 @@ pub fn update_dir_address(dir: &mut [Descriptor]) {
 	}
 }
 
+/// Rebuilds a damaged directory.
+///
+/// The tree structure is recovered the same way lookups see it, with the child counts clamped by [`next_sibling`](fn.next_sibling.html).
+/// Descriptors with an empty name or a name containing path separators are dropped together with their children.
+/// File descriptors rejected by `is_valid_file` are dropped.
+/// The child counts of the remaining directories are recalculated.
+///
+/// Returns the repaired directory and the full path and descriptor of everything that was dropped.
+pub fn repair<F: FnMut(&Descriptor) -> bool>(dir: &[Descriptor], mut is_valid_file: F) -> (Vec<Descriptor>, Vec<(Vec<u8>, Descriptor)>) {
+	let mut fixed: Vec<Descriptor> = Vec::new();
+	let mut dropped = Vec::new();
+	// The end index, path length and repaired index (if kept) of every directory being walked
+	let mut stack: Vec<(usize, usize, Option<usize>)> = vec![(dir.len(), 0, None)];
+	let mut path = Vec::new();
+	for (i, desc) in dir.iter().enumerate() {
+		while stack.last().unwrap().0 <= i {
+			let (_, _, index) = stack.pop().unwrap();
+			if let Some(index) = index {
+				fixed[index].content_size = (fixed.len() - index - 1) as u32;
+			}
+		}
+		let &(end, path_len, parent) = stack.last().unwrap();
+		path.truncate(path_len);
+		if path_len != 0 {
+			path.push(b'/');
+		}
+		path.extend_from_slice(desc.name());
+
+		let parent_kept = parent.is_some() || stack.len() == 1;
+		let name = desc.name();
+		let name_ok = !name.is_empty() && desc.name_len() <= 31 && !name.iter().any(|&chr| chr == b'/' || chr == b'\\');
+		let keep = parent_kept && name_ok && (desc.is_dir() || is_valid_file(desc));
+
+		if desc.is_dir() {
+			stack.push((next_sibling(desc, i, end), path.len(), if keep { Some(fixed.len()) } else { None }));
+		}
+		if keep {
+			fixed.push(*desc);
+		}
+		else {
+			dropped.push((path.clone(), *desc));
+		}
+	}
+	for (_, _, index) in stack {
+		if let Some(index) = index {
+			fixed[index].content_size = (fixed.len() - index - 1) as u32;
+		}
+	}
+	(fixed, dropped)
+}
+
 //----------------------------------------------------------------
 
 #[cfg(test)]
@@ -597,6 +1415,25 @@ mod tests {
 		assert_eq!(dir, result);
 	}
 
+	#[test]
+	fn test_create_existing() {
+		let mut dir = Vec::new();
+		create(&mut dir, b"A/FOO").content_type = 1;
+		create(&mut dir, b"BAR");
+		create(&mut dir, b"A/BAZ");
+
+		// Creating an existing descriptor returns it unchanged
+		assert_eq!(create(&mut dir, b"A/FOO").content_type, 1);
+
+		let result = [
+			Descriptor::dir(b"A", 2),
+			Descriptor::file(b"FOO"),
+			Descriptor::dir(b"BAZ", 0),
+			Descriptor::dir(b"BAR", 0),
+		];
+		assert_eq!(dir, result);
+	}
+
 	#[test]
 	fn test_find_encrypted() {
 		let mut dir = example_dir();
@@ -606,4 +1443,94 @@ mod tests {
 		let found = find_encrypted(&dir, b"a/b/c/file", &nonce, &key);
 		assert!(matches!(found, Some(_)));
 	}
+
+	#[test]
+	fn test_walk_encrypted() {
+		let plain = example_dir();
+		let mut dir = plain.clone();
+		let key = [42, 13];
+		let nonce = [31415, 2781];
+		crypt::encrypt_dir_inplace(&mut dir, &nonce, &key);
+		let expected: Vec<_> = walk(&plain).collect();
+		assert_eq!(walk_encrypted(&dir, &nonce, &key).collect::<Vec<_>>(), expected);
+		assert_eq!(expected[4].0, b"a/b/c/file");
+
+		// Rejecting a directory prunes its children
+		let paths: Vec<_> = walk(&plain).filter_entry(|path, _| path != b"a/b").map(|(path, _)| path).collect();
+		assert_eq!(paths, [&b"before"[..], b"a"]);
+	}
+
+	#[test]
+	fn test_glob() {
+		let mut dir = Vec::new();
+		for path in [&b"sounds/a/step.ogg"[..], b"sounds/a/jump.wav", b"sounds/bb/step.ogg", b"sounds/music.ogg", b"textures/x/y/grass.png", b"textures/stone.png"] {
+			create(&mut dir, path).content_type = 1;
+		}
+		let paths = |pattern: &[u8]| glob(&dir, pattern).into_iter().map(|(path, _)| String::from_utf8(path).unwrap()).collect::<Vec<_>>();
+
+		assert_eq!(paths(b"sounds/?/*.ogg"), ["sounds/a/step.ogg"]);
+		assert_eq!(paths(b"sounds/*/step.ogg"), ["sounds/a/step.ogg", "sounds/bb/step.ogg"]);
+		assert_eq!(paths(b"textures/**/*.png"), ["textures/x/y/grass.png", "textures/stone.png"]);
+		assert_eq!(paths(b"**/*.wav"), ["sounds/a/jump.wav"]);
+		assert_eq!(paths(b"textures/**"), ["textures/x", "textures/x/y", "textures/x/y/grass.png", "textures/stone.png"]);
+		assert_eq!(paths(b"sounds\\music.ogg"), ["sounds/music.ogg"]);
+		assert_eq!(paths(b"*"), ["sounds", "textures"]);
+		assert!(paths(b"sounds/*.png").is_empty());
+
+		// Paths reachable through several expansions of `**` are found once
+		assert_eq!(paths(b"**/x/**/*.png"), ["textures/x/y/grass.png"]);
+		assert_eq!(paths(b"**/**/*.png"), ["textures/x/y/grass.png", "textures/stone.png"]);
+		assert_eq!(paths(b"**/y/**"), ["textures/x/y/grass.png"]);
+		let mut dir = Vec::new();
+		create(&mut dir, b"a/x/x/f.png").content_type = 1;
+		assert_eq!(glob(&dir, b"**/x/**/*.png").len(), 1);
+		assert_eq!(glob(&dir, b"**\\**\\*.png").len(), 1);
+		let removed = remove_glob(&mut dir, b"**/**/x");
+		assert_eq!(removed.len(), 3);
+
+		let plain = glob(&dir, b"**/*.ogg");
+		let key = [42, 13];
+		let nonce = [31415, 2781];
+		crypt::encrypt_dir_inplace(&mut dir, &nonce, &key);
+		assert_eq!(glob_encrypted(&dir, b"**/*.ogg", &nonce, &key), plain);
+	}
+
+	#[test]
+	fn test_sort() {
+		let mut dir = Vec::new();
+		for path in [&b"z/b"[..], b"z/a/2", b"z/a/1", b"m", b"a", b"a/c"] {
+			create(&mut dir, path).content_type = 1;
+		}
+		sort(&mut dir);
+		let paths: Vec<_> = walk(&dir).map(|(path, _)| String::from_utf8(path).unwrap()).collect();
+		assert_eq!(paths, ["a", "a/c", "z", "z/a", "z/a/1", "z/a/2", "z/b", "a", "m"]);
+		// The directories precede the files
+		assert!(dir[0].is_dir() && dir[7].is_file());
+
+		let index = SiblingIndex::new(&dir);
+		// Lookups find the same descriptors as the linear search
+		for (path, _) in walk(&dir) {
+			let i = index.find(&dir, 0, &path).unwrap();
+			assert_eq!(Some(&dir[i]), find_desc(&dir, &path));
+			assert_eq!(find_by(dir.len(), &path, true, Case::Sensitive, |i| dir[i]), Some((i, dir[i])));
+		}
+		assert_eq!(index.find(&dir, 0, b"z/a/3"), None);
+		assert_eq!(index.find(&dir, 0, b"b"), None);
+		assert_eq!(index.find(&dir, 0, b""), None);
+		assert_eq!(find_by(dir.len(), b"b", true, Case::Sensitive, |i| dir[i]), None);
+		update_dir_address(&mut dir);
+		let z = find_desc(&dir, b"z").unwrap();
+		assert_eq!(index.find(&dir, z.section.offset as usize, b"a/1"), Some(4));
+
+		// The files of a sorted directory are binary searched
+		let mut dir = Vec::new();
+		for i in 0..1000 {
+			create(&mut dir, format!("{:03}", i).as_bytes()).content_type = 1;
+		}
+		sort(&mut dir);
+		let mut reads = 0;
+		let found = find_by(dir.len(), b"733", true, Case::Sensitive, |i| { reads += 1; dir[i] });
+		assert_eq!(found.map(|(i, _)| i), Some(733));
+		assert!(reads <= 12, "{} reads", reads);
+	}
 }
@@ -0,0 +1,86 @@
+use std::{error, fmt, io};
+use crate::*;
+
+/// Error type for the reader and editor APIs.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Error {
+	/// The header cannot be decrypted with the given key.
+	BadKey,
+	/// The header is decrypted but its version is not supported.
+	BadVersion,
+	/// The PAK file is smaller than its header or directory requires.
+	Truncated,
+	/// A section lies outside the PAK file or overlaps with the header.
+	OutOfRange,
+	/// No descriptor exists at the given path.
+	NotFound,
+	/// The descriptor is not a file.
+	NotAFile,
+	/// A name differs only by case from an existing name in the same directory.
+	CaseConflict,
+	/// The path is empty, escapes the root or contains an invalid name.
+	InvalidPath,
+	/// A descriptor already exists at the given path.
+	AlreadyExists,
+	/// The patch is corrupt, uses a different key or does not apply to the PAK file.
+	BadPatch,
+}
+
+/// Result type for the reader and editor APIs.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+	fn as_str(&self) -> &'static str {
+		match self {
+			Error::BadKey => "bad key",
+			Error::BadVersion => "bad version",
+			Error::Truncated => "truncated PAK file",
+			Error::OutOfRange => "section out of range",
+			Error::NotFound => "path not found",
+			Error::NotAFile => "not a file",
+			Error::CaseConflict => "name differs only by case",
+			Error::InvalidPath => "invalid path",
+			Error::AlreadyExists => "path already exists",
+			Error::BadPatch => "patch does not apply",
+		}
+	}
+}
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+impl error::Error for Error {}
+
+impl From<Error> for io::Error {
+	fn from(err: Error) -> io::Error {
+		let kind = match err {
+			Error::Truncated => io::ErrorKind::UnexpectedEof,
+			Error::NotFound => io::ErrorKind::NotFound,
+			Error::NotAFile => io::ErrorKind::InvalidInput,
+			Error::CaseConflict | Error::AlreadyExists => io::ErrorKind::AlreadyExists,
+			Error::InvalidPath => io::ErrorKind::InvalidInput,
+			_ => io::ErrorKind::InvalidData,
+		};
+		io::Error::new(kind, err)
+	}
+}
+
+/// Checks the decrypted info header given the size of the PAK file in blocks.
+pub(crate) fn check_info(info: &InfoHeader, blocks_len: usize) -> Result<()> {
+	if info.version != InfoHeader::VERSION {
+		// Unknown flags are always written as zero, garbage implies the wrong key was used to decrypt the header
+		return Err(if info.flags & !InfoHeader::FLAGS == 0 { Error::BadVersion } else { Error::BadKey });
+	}
+	if info.flags & !InfoHeader::FLAGS != 0 {
+		return Err(Error::BadVersion);
+	}
+	if (info.directory.offset as usize) < Header::BLOCKS_LEN {
+		return Err(Error::OutOfRange);
+	}
+	let dir_end = info.directory.offset as usize + info.directory.size as usize * Descriptor::BLOCKS_LEN;
+	if dir_end > blocks_len {
+		return Err(Error::Truncated);
+	}
+	Ok(())
+}
@@ -0,0 +1,133 @@
+use std::{cmp, io};
+use crate::*;
+
+/// Handle for reading the contents of a file in a PAK file.
+///
+/// Implements `Read`, `Seek` and `BufRead` and decrypts the contents lazily as they are read.
+/// Created through [`MemoryReader::open`](struct.MemoryReader.html#method.open) and friends.
+#[derive(Clone)]
+pub struct FileReader<'a> {
+	blocks: &'a [u8],
+	key: Key,
+	nonce: Block,
+	size: u64,
+	pos: u64,
+	// Single decrypted block backing the BufRead implementation
+	buf: [u8; BLOCK_SIZE],
+	buf_index: u64,
+}
+impl<'a> FileReader<'a> {
+	/// Creates a file handle given the encrypted bytes of the file's section.
+	///
+	/// The section must be validated to contain at least `content_size` bytes.
+	pub(crate) fn new(blocks: &'a [u8], key: &Key, desc: &Descriptor) -> FileReader<'a> {
+		debug_assert!(blocks.len() >= desc.content_size as usize);
+		FileReader {
+			blocks,
+			key: *key,
+			nonce: desc.section.nonce,
+			size: desc.content_size as u64,
+			pos: 0,
+			buf: [0; BLOCK_SIZE],
+			buf_index: u64::MAX,
+		}
+	}
+	/// Returns the size of the file in bytes.
+	#[inline]
+	pub fn len(&self) -> u64 {
+		self.size
+	}
+	/// Returns if the file is empty.
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.size == 0
+	}
+	/// Returns the current position in the file.
+	#[inline]
+	pub fn position(&self) -> u64 {
+		self.pos
+	}
+}
+impl<'a> io::Read for FileReader<'a> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		if self.pos >= self.size {
+			return Ok(0);
+		}
+		let n = cmp::min(buf.len() as u64, self.size - self.pos) as usize;
+		crypt::decrypt_data(self.blocks, &self.nonce, &self.key, self.pos as usize, &mut buf[..n]);
+		self.pos += n as u64;
+		Ok(n)
+	}
+}
+impl<'a> io::BufRead for FileReader<'a> {
+	fn fill_buf(&mut self) -> io::Result<&[u8]> {
+		if self.pos >= self.size {
+			return Ok(&[]);
+		}
+		// Decrypt the block containing the current position if not already available
+		let block_index = self.pos / BLOCK_SIZE as u64;
+		let block_start = block_index * BLOCK_SIZE as u64;
+		let block_end = cmp::min(self.size, block_start + BLOCK_SIZE as u64);
+		let len = (block_end - block_start) as usize;
+		if self.buf_index != block_index {
+			crypt::decrypt_data(self.blocks, &self.nonce, &self.key, block_start as usize, &mut self.buf[..len]);
+			self.buf_index = block_index;
+		}
+		Ok(&self.buf[(self.pos - block_start) as usize..len])
+	}
+	fn consume(&mut self, amt: usize) {
+		self.pos = cmp::min(self.size, self.pos + amt as u64);
+	}
+}
+impl<'a> io::Seek for FileReader<'a> {
+	fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+		self.pos = seek_pos(self.pos, self.size, pos)?;
+		Ok(self.pos)
+	}
+}
+
+/// Calculates the new position for seeking in a file with the given size.
+pub(crate) fn seek_pos(current: u64, size: u64, pos: io::SeekFrom) -> io::Result<u64> {
+	let (base, offset) = match pos {
+		io::SeekFrom::Start(offset) => return Ok(offset),
+		io::SeekFrom::End(offset) => (size, offset),
+		io::SeekFrom::Current(offset) => (current, offset),
+	};
+	let new_pos = if offset >= 0 { base.checked_add(offset as u64) } else { base.checked_sub(offset.wrapping_neg() as u64) };
+	match new_pos {
+		Some(new_pos) => Ok(new_pos),
+		None => Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position")),
+	}
+}
+
+#[test]
+fn test_read_seek() {
+	use std::io::{BufRead, Read, Seek, SeekFrom};
+
+	let key = &[13, 42];
+	let data = b"first line\nsecond line\nthird and last line";
+	let mut edit = MemoryEditor::new();
+	edit.create_file(b"lines.txt", data, key);
+	let (blocks, _) = edit.finish(key);
+	let reader = MemoryReader::from_blocks(&blocks, key);
+	let desc = reader.find(b"lines.txt").unwrap();
+
+	let mut file = reader.open(&desc).unwrap();
+	assert_eq!(file.len(), data.len() as u64);
+	let lines = (&mut file).lines().collect::<io::Result<Vec<_>>>().unwrap();
+	assert_eq!(lines, ["first line", "second line", "third and last line"]);
+
+	assert_eq!(file.seek(SeekFrom::Start(6)).unwrap(), 6);
+	let mut word = [0; 4];
+	file.read_exact(&mut word).unwrap();
+	assert_eq!(&word, b"line");
+
+	assert_eq!(file.seek(SeekFrom::End(-4)).unwrap(), data.len() as u64 - 4);
+	let mut tail = Vec::new();
+	file.read_to_end(&mut tail).unwrap();
+	assert_eq!(tail, b"line");
+
+	assert!(file.seek(SeekFrom::Current(-100)).is_err());
+	assert_eq!(file.seek(SeekFrom::End(10)).unwrap(), data.len() as u64 + 10);
+	assert_eq!(file.read(&mut word).unwrap(), 0);
+}
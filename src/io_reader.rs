@@ -1,48 +1,379 @@
-use std::io::{self, Read};
+use std::{cmp, io::{self, Read, Seek, SeekFrom}};
+use crate::lru::Lru;
 use crate::*;
 
 /// Reads a PAK file from file stream.
 ///
-/// Returns `InvalidData` if the file does not encode a PAK file.
+/// Reading stops after the directory referenced by the header and its path index.
+/// Blocks before the directory may contain stale directories of earlier appends, they are unreferenced and read as file contents.
+/// Blocks after the directory are left unread, they may be left behind by an interrupted append, see [`MemoryEditor::finish_append`](struct.MemoryEditor.html#method.finish_append).
+///
+/// Returns an error converted from [`Error`](enum.Error.html) if the file does not encode a PAK file.
 pub fn read<F: Read>(mut file: F, key: &Key) -> io::Result<Vec<Block>> {
 	// Read and decrypt the header block
 	let mut header = Header::zeroed();
 	file.read_exact(header.as_bytes_mut())?;
 	let info = crypt::decrypt_header(&header, key);
-	if info.version != InfoHeader::VERSION {
-		return Err(io::Error::from(io::ErrorKind::InvalidData));
-	}
+	// The size of the file is not known in advance, check everything but the directory range
+	error::check_info(&info, usize::MAX)?;
 	// Use information from the header to calculate the total size of the PAK file
-	// This code assumes the directory is the very last thing in the PAK file
+	// The directory (and its path index) referenced by the header ends the PAK file, anything after it is ignored
 	let total_blocks = usize::max(Header::BLOCKS_LEN, info.directory.offset as usize + info.directory.size as usize * Descriptor::BLOCKS_LEN);
 	let mut blocks = vec![Block::default(); total_blocks];
 	// Copy the header into the output since it's already read from the file
 	// Then read the rest of the PAK file
 	blocks[..Header::BLOCKS_LEN].as_bytes_mut().copy_from_slice(header.as_bytes());
 	file.read_exact(blocks[Header::BLOCKS_LEN..].as_bytes_mut())?;
+	// The size of the path index is stored in its first block
+	if info.has_path_index() {
+		let mut first = Block::default();
+		file.read_exact(first.as_bytes_mut())?;
+		let capacity = crypt::decrypt_block_at(&[first][..], 0, &path_index::nonce(&info.directory), key)[0];
+		let max_capacity = usize::max(1, info.directory.size as usize * 2).next_power_of_two() as u64;
+		if !capacity.is_power_of_two() || capacity > max_capacity {
+			return Err(Error::OutOfRange.into());
+		}
+		blocks.push(first);
+		let start = blocks.len();
+		blocks.resize(start + capacity as usize, Block::default());
+		file.read_exact(blocks[start..].as_bytes_mut())?;
+	}
 	Ok(blocks)
 }
 
-/*
-pub struct IoReader<F: Read + Seek> {
+fn read_exact_at<F: Read + Seek>(file: &mut F, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+	file.seek(SeekFrom::Start(offset))?;
+	file.read_exact(buf)
+}
+
+/// Statistics of the caches of an [`IoReader`](struct.IoReader.html).
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct CacheStats {
+	/// Number of descriptors found in the cache.
+	pub desc_hits: u64,
+	/// Number of descriptors read from the stream.
+	pub desc_misses: u64,
+	/// Number of data blocks found in the cache.
+	pub block_hits: u64,
+	/// Number of data blocks read from the stream.
+	pub block_misses: u64,
+}
+
+/// Directory iterator.
+pub struct IoReadIter<'a, F> {
+	io_reader: &'a mut IoReader<F>,
+	start: u32,
+	end: u32,
+}
+impl<'a, F: Read + Seek> Iterator for IoReadIter<'a, F> {
+	type Item = io::Result<Descriptor>;
+	fn next(&mut self) -> Option<io::Result<Descriptor>> {
+		if self.start >= self.end {
+			return None;
+		}
+		let desc = match self.io_reader.read_desc(self.start as usize) {
+			Ok(desc) => desc,
+			Err(err) => {
+				self.start = self.end;
+				return Some(Err(err));
+			},
+		};
+		self.start = directory::next_sibling(&desc, self.start as usize, self.end as usize) as u32;
+		Some(Ok(desc))
+	}
+}
+
+/// Reads a PAK file from a seekable stream with on-the-fly decryption.
+///
+/// The directory is read and decrypted one descriptor at the time.
+/// Decrypted descriptors and data blocks are kept in least recently used caches to avoid repeated reads and decryption.
+pub struct IoReader<F> {
 	file: F,
 	key: Key,
 	info: InfoHeader,
+	descs: Lru<usize, Descriptor>,
+	// Decrypted data blocks by their block offset in the stream and their counter nonce
+	blocks: Lru<(u32, Block), [u8; BLOCK_SIZE]>,
 }
-
 impl<F: Read + Seek> IoReader<F> {
-	pub fn new(mut file: F, key: &Key) -> io::Result<IoReader<F>> {
-		file.seek(SeekFrom::Start(0))?;
+	/// Default number of cached descriptors.
+	pub const DESC_CAPACITY: usize = 256;
+	/// Default number of cached data blocks.
+	pub const BLOCK_CAPACITY: usize = 1024;
 
+	/// Reads the header of the PAK file.
+	///
+	/// Returns an error converted from [`Error`](enum.Error.html) if the stream does not encode a PAK file.
+	pub fn new(mut file: F, key: &Key) -> io::Result<IoReader<F>> {
 		let mut header = Header::zeroed();
-		file.read_exact(header.as_bytes_mut())?;
-
+		read_exact_at(&mut file, 0, header.as_bytes_mut())?;
 		let info = crypt::decrypt_header(&header, key);
-		Ok(IoReader { file, key: *key, info })
+		// The size of the stream is not known in advance, check everything but the directory range
+		error::check_info(&info, usize::MAX)?;
+		Ok(IoReader {
+			file,
+			key: *key,
+			info,
+			descs: Lru::new(IoReader::<F>::DESC_CAPACITY),
+			blocks: Lru::new(IoReader::<F>::BLOCK_CAPACITY),
+		})
+	}
+	/// Sets the number of cached descriptors and data blocks.
+	///
+	/// Clears the caches but keeps the statistics, a capacity of zero disables caching.
+	pub fn cache_capacity(mut self, descs: usize, blocks: usize) -> IoReader<F> {
+		self.descs.set_capacity(descs);
+		self.blocks.set_capacity(blocks);
+		self
+	}
+	/// Returns the cache statistics.
+	pub fn cache_stats(&self) -> CacheStats {
+		CacheStats {
+			desc_hits: self.descs.hits,
+			desc_misses: self.descs.misses,
+			block_hits: self.blocks.hits,
+			block_misses: self.blocks.misses,
+		}
+	}
+	/// Clears the caches and resets the statistics.
+	pub fn clear_cache(&mut self) {
+		self.descs = Lru::new(self.descs.capacity());
+		self.blocks = Lru::new(self.blocks.capacity());
+	}
+	/// Unwraps the underlying stream.
+	pub fn into_inner(self) -> F {
+		self.file
+	}
+	/// Returns if this IoReader contains no files or directories.
+	pub fn is_empty(&self) -> bool {
+		self.info.directory.size == 0
+	}
+	fn read_desc(&mut self, index: usize) -> io::Result<Descriptor> {
+		if let Some(desc) = self.descs.get(&index) {
+			return Ok(*desc);
+		}
+		let mut desc = Descriptor::zeroed();
+		let offset = (self.info.directory.offset as u64 + (index * Descriptor::BLOCKS_LEN) as u64) * BLOCK_SIZE as u64;
+		read_exact_at(&mut self.file, offset, desc.as_bytes_mut())?;
+		let nonce = crypt::counter(&self.info.directory.nonce, index * Descriptor::BLOCKS_LEN);
+		let desc = crypt::decrypt_desc(&desc, &nonce, &self.key);
+		self.descs.insert(index, desc);
+		Ok(desc)
+	}
+	/// Finds a descriptor by its path.
+	pub fn find(&mut self, path: &[u8]) -> io::Result<Option<Descriptor>> {
+		self.find_range(0, self.info.directory.size as usize, path)
+	}
+	/// Finds a descriptor by its path starting from the given root directory.
+	pub fn find_sub(&mut self, root: &Descriptor, path: &[u8]) -> io::Result<Option<Descriptor>> {
+		let range = root.section.range_usize();
+		if range.start > range.end || range.end > self.info.directory.size as usize {
+			return Ok(None);
+		}
+		self.find_range(range.start, range.end, path)
+	}
+	fn find_range(&mut self, start: usize, end: usize, path: &[u8]) -> io::Result<Option<Descriptor>> {
+		// Invalid paths are never found
+		let path = match path::normalize(path) {
+			Ok(path) => path,
+			Err(_) => return Ok(None),
+		};
+		let sorted = self.info.is_sorted();
+		let found = directory::try_find_by(end - start, &path, sorted, directory::Case::Sensitive, |i| self.read_desc(start + i))?;
+		Ok(found.map(|(_, desc)| desc))
+	}
+	/// Returns if the descriptor is a valid file.
+	///
+	/// Unlike the `MemoryReader` the section is not checked against the size of the stream.
+	pub fn is_valid_file(&self, desc: &Descriptor) -> bool {
+		desc.content_type != 0 &&
+		desc.section.offset >= Header::BLOCKS_LEN as u32 &&
+		desc.section.offset.checked_add(desc.section.size).is_some() &&
+		bytes2blocks(desc.content_size) <= desc.section.size
 	}
+	/// Decrypts the contents of the given file descriptor.
+	///
+	/// Returns `InvalidInput` if the descriptor is not a valid file.
+	pub fn read_data(&mut self, desc: &Descriptor) -> io::Result<Vec<u8>> {
+		let mut bytes = vec![0; desc.content_size as usize];
+		self.read_into(desc, 0, &mut bytes)?;
+		Ok(bytes)
+	}
+	/// Decrypts the contents of the given file descriptor into the dest buffer.
+	/// Given a byte offset into the file where to start decrypting.
+	///
+	/// Returns `InvalidInput` if the descriptor is not a valid file or the range is outside the file's section.
+	pub fn read_into(&mut self, desc: &Descriptor, byte_offset: usize, dest: &mut [u8]) -> io::Result<()> {
+		let byte_end = match byte_offset.checked_add(dest.len()) {
+			Some(byte_end) if self.is_valid_file(desc) && byte_end <= desc.section.size as usize * BLOCK_SIZE => byte_end,
+			_ => return Err(io::Error::from(io::ErrorKind::InvalidInput)),
+		};
+		let block_start = byte_offset / BLOCK_SIZE;
+		let block_end = byte_end.div_ceil(BLOCK_SIZE);
+		let mut i = block_start;
+		while i < block_end {
+			// Copy cached blocks
+			let nonce = crypt::counter(&desc.section.nonce, i);
+			if let Some(block) = self.blocks.get(&(desc.section.offset + i as u32, nonce)) {
+				copy_block(block, i, byte_offset, dest);
+				i += 1;
+				continue;
+			}
+			// Read and decrypt the run of blocks until the next cached block in one go
+			let mut run_end = i + 1;
+			while run_end < block_end && !self.blocks.contains_key(&(desc.section.offset + run_end as u32, crypt::counter(&desc.section.nonce, run_end))) {
+				run_end += 1;
+			}
+			// Every block in the run is read from the stream, the lookup above only counted the first
+			self.blocks.misses += (run_end - i - 1) as u64;
+			let mut run = vec![0u8; (run_end - i) * BLOCK_SIZE];
+			read_exact_at(&mut self.file, (desc.section.offset as u64 + i as u64) * BLOCK_SIZE as u64, &mut run)?;
+			let mut plain = vec![0u8; run.len()];
+			crypt::decrypt_data(&run[..], &nonce, &self.key, 0, &mut plain);
+			for (j, chunk) in plain.chunks_exact(BLOCK_SIZE).enumerate() {
+				let mut block = [0; BLOCK_SIZE];
+				block.copy_from_slice(chunk);
+				copy_block(&block, i + j, byte_offset, dest);
+				self.blocks.insert((desc.section.offset + (i + j) as u32, crypt::counter(&desc.section.nonce, i + j)), block);
+			}
+			i = run_end;
+		}
+		Ok(())
+	}
+	/// Opens the given file descriptor for streaming its decrypted contents.
+	///
+	/// Returns `None` if the descriptor is not a valid file.
+	pub fn open(&mut self, desc: &Descriptor) -> Option<IoFileReader<'_, F>> {
+		if !self.is_valid_file(desc) {
+			return None;
+		}
+		Some(IoFileReader { io_reader: self, desc: *desc, pos: 0, buf: [0; BLOCK_SIZE], buf_index: u64::MAX })
+	}
+	pub fn iter(&mut self, desc: &Descriptor) -> IoReadIter<'_, F> {
+		IoReadIter {
+			start: desc.section.offset,
+			end: desc.section.offset.saturating_add(desc.section.size),
+			io_reader: self,
+		}
+	}
+}
 
-	pub fn find(&self, path: &[u8]) -> Option<Descriptor> {
-		unimplemented!()
+/// Handle for reading the contents of a file from an [`IoReader`](struct.IoReader.html).
+///
+/// Implements `Read`, `Seek` and `BufRead`, the contents are read and decrypted through the reader's block cache.
+pub struct IoFileReader<'a, F> {
+	io_reader: &'a mut IoReader<F>,
+	desc: Descriptor,
+	pos: u64,
+	// Single decrypted block backing the BufRead implementation
+	buf: [u8; BLOCK_SIZE],
+	buf_index: u64,
+}
+impl<'a, F> IoFileReader<'a, F> {
+	/// Returns the size of the file in bytes.
+	#[inline]
+	pub fn len(&self) -> u64 {
+		self.desc.content_size as u64
+	}
+	/// Returns if the file is empty.
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.desc.content_size == 0
+	}
+	/// Returns the current position in the file.
+	#[inline]
+	pub fn position(&self) -> u64 {
+		self.pos
+	}
+}
+impl<'a, F: Read + Seek> Read for IoFileReader<'a, F> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let size = self.len();
+		if self.pos >= size {
+			return Ok(0);
+		}
+		let n = cmp::min(buf.len() as u64, size - self.pos) as usize;
+		self.io_reader.read_into(&self.desc, self.pos as usize, &mut buf[..n])?;
+		self.pos += n as u64;
+		Ok(n)
+	}
+}
+impl<'a, F: Read + Seek> io::BufRead for IoFileReader<'a, F> {
+	fn fill_buf(&mut self) -> io::Result<&[u8]> {
+		let size = self.len();
+		if self.pos >= size {
+			return Ok(&[]);
+		}
+		// Decrypt the block containing the current position if not already available
+		let block_index = self.pos / BLOCK_SIZE as u64;
+		let block_start = block_index * BLOCK_SIZE as u64;
+		let len = (cmp::min(size, block_start + BLOCK_SIZE as u64) - block_start) as usize;
+		if self.buf_index != block_index {
+			self.io_reader.read_into(&self.desc, block_start as usize, &mut self.buf[..len])?;
+			self.buf_index = block_index;
+		}
+		Ok(&self.buf[(self.pos - block_start) as usize..len])
 	}
+	fn consume(&mut self, amt: usize) {
+		self.pos = cmp::min(self.len(), self.pos + amt as u64);
+	}
+}
+impl<'a, F> Seek for IoFileReader<'a, F> {
+	fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+		self.pos = file_reader::seek_pos(self.pos, self.len(), pos)?;
+		Ok(self.pos)
+	}
+}
+
+// Copies the part of the decrypted block at block index i overlapping the dest buffer at the byte offset
+fn copy_block(block: &[u8; BLOCK_SIZE], i: usize, byte_offset: usize, dest: &mut [u8]) {
+	let start = cmp::max(i * BLOCK_SIZE, byte_offset);
+	let end = cmp::min((i + 1) * BLOCK_SIZE, byte_offset + dest.len());
+	dest[start - byte_offset..end - byte_offset].copy_from_slice(&block[start - i * BLOCK_SIZE..end - i * BLOCK_SIZE]);
+}
+
+#[test]
+fn test_io_reader_cache() {
+	let key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	edit.create_file(b"dir/a", b"small file", key);
+	let big: Vec<u8> = (0..100u8).collect();
+	edit.create_file(b"dir/b", &big, key);
+	let (blocks, _) = edit.finish(key);
+
+	let mut reader = IoReader::new(io::Cursor::new(blocks.as_bytes().to_vec()), key).unwrap().cache_capacity(16, 4);
+	let a = reader.find(b"dir/a").unwrap().unwrap();
+	assert_eq!(reader.read_data(&a).unwrap(), b"small file");
+	let b = reader.find(b"dir/b").unwrap().unwrap();
+	assert_eq!(reader.cache_stats().desc_hits, 2);
+
+	let mut dest = [0; 20];
+	reader.read_into(&b, 10, &mut dest).unwrap();
+	assert_eq!(dest[..], big[10..30]);
+	assert_eq!(reader.cache_stats().block_misses, 3);
+	reader.read_into(&b, 12, &mut dest[..5]).unwrap();
+	assert_eq!(dest[..5], big[12..17]);
+	assert_eq!(reader.cache_stats().block_misses, 3);
+	assert_eq!(reader.read_data(&b).unwrap(), big);
+
+	let dir = reader.find(b"dir").unwrap().unwrap();
+	let names: Vec<_> = reader.iter(&dir).map(|desc| desc.unwrap().name().to_vec()).collect();
+	assert_eq!(names, [b"a", b"b"]);
+
+	// The file handle reads through the cache
+	let hits = reader.cache_stats().block_hits;
+	let mut file = reader.open(&b).unwrap();
+	file.seek(SeekFrom::Start(95)).unwrap();
+	let mut tail = Vec::new();
+	file.read_to_end(&mut tail).unwrap();
+	assert_eq!(tail, big[95..]);
+	assert!(reader.cache_stats().block_hits > hits);
+	assert!(reader.open(&dir).is_none());
+
+	// Disabled caches still work
+	let mut reader = reader.cache_capacity(0, 0);
+	let hits = reader.cache_stats().block_hits;
+	assert_eq!(reader.read_data(&b).unwrap(), big);
+	assert_eq!(reader.read_data(&b).unwrap(), big);
+	assert_eq!(reader.cache_stats().block_hits, hits);
 }
-*/
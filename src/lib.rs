@@ -24,13 +24,49 @@ mod speck128;
 mod crypt;
 pub mod directory;
 
+mod error;
+pub use self::error::{Error, Result};
+
+pub mod path;
+mod path_index;
+
+pub mod verify;
+pub mod diff;
+pub mod patch;
+pub mod merge;
+
 mod memory_reader;
 mod memory_editor;
-pub use self::memory_reader::{MemoryReader, MemoryReadIter};
-pub use self::memory_editor::{MemoryEditor, MemoryEditFile};
+pub use self::memory_reader::{MemoryReader, MemoryReadIter, CachedMemoryReader};
+pub use self::memory_editor::{MemoryEditor, MemoryEditFile, MemoryEditWriter};
+
+mod file_reader;
+pub use self::file_reader::FileReader;
+
+mod bytes_reader;
+pub use self::bytes_reader::{BytesReader, BytesReadIter};
+
+#[cfg(feature = "mmap")]
+mod mmap_reader;
+#[cfg(feature = "mmap")]
+pub use self::mmap_reader::MmapReader;
+
+#[cfg(feature = "async")]
+mod async_reader;
+#[cfg(feature = "async")]
+pub use self::async_reader::{AsyncReader, AsyncReadIter, AsyncFileReader};
+
+#[cfg(any(unix, windows))]
+mod shared_reader;
+#[cfg(any(unix, windows))]
+pub use self::shared_reader::{SharedReader, SharedFileReader};
 
+mod lru;
 mod io_reader;
-pub use self::io_reader::read;
+pub use self::io_reader::{read, IoReader, IoReadIter, IoFileReader, CacheStats};
+
+mod overlay_reader;
+pub use self::overlay_reader::{OverlayReader, OverlayDesc};
 
 pub type Block = [u64; 2];
 pub type Key = [u64; 2];
@@ -119,8 +155,11 @@ macro_rules! impl_blocks {
 pub struct InfoHeader {
 	/// Version info value, should be equal to `Header::VERSION_INFO`.
 	pub version: u32,
-	/// Padding...
-	pub unused: [u32; 1],
+	/// Flags describing how the PAK file was written, see `InfoHeader::FLAG_SORTED`.
+	///
+	/// Unknown flags are always written as zero.
+	/// This field was named `unused` and typed `[u32; 1]` before version 0.2.
+	pub flags: u32,
 	/// The section object describing the location of the directory.
 	///
 	/// Special note: the section size specifies the number of `Descriptors` not the number of blocks.
@@ -131,6 +170,23 @@ unsafe impl Pod for InfoHeader {}
 impl InfoHeader {
 	/// Current expected version number.
 	pub const VERSION: u32 = 0;
+	/// The siblings of every directory are sorted, directories first and files last, each by name.
+	pub const FLAG_SORTED: u32 = 1;
+	/// A hashed path index follows the directory.
+	pub const FLAG_PATH_INDEX: u32 = 2;
+	/// All the flags known to this version.
+	pub const FLAGS: u32 = InfoHeader::FLAG_SORTED | InfoHeader::FLAG_PATH_INDEX;
+
+	/// Returns if the siblings of every directory are sorted, see `InfoHeader::FLAG_SORTED`.
+	#[inline]
+	pub fn is_sorted(&self) -> bool {
+		self.flags & InfoHeader::FLAG_SORTED != 0
+	}
+	/// Returns if a hashed path index follows the directory.
+	#[inline]
+	pub fn has_path_index(&self) -> bool {
+		self.flags & InfoHeader::FLAG_PATH_INDEX != 0
+	}
 }
 
 impl_blocks!(InfoHeader; mem::size_of::<InfoHeader>() / BLOCK_SIZE);
@@ -162,6 +218,10 @@ pub struct Descriptor {
 }
 unsafe impl Pod for Descriptor {}
 impl Descriptor {
+	/// Content type of whiteout file descriptors.
+	///
+	/// A whiteout is an empty file which hides the path in lower layers of an [`OverlayReader`](struct.OverlayReader.html).
+	pub const WHITEOUT: u32 = u32::MAX;
 	/// Creates a new empty descriptor with the given name, content type and size.
 	///
 	/// The descriptor is a directory descriptor if its `content_type` is zero.
@@ -188,7 +248,8 @@ impl Descriptor {
 		Descriptor::new(name, 0, len)
 	}
 	fn name_len(&self) -> usize {
-		self.name_buf.len() - self.name_buf[self.name_buf.len() - 1] as usize
+		// Corrupt descriptors must not underflow
+		self.name_buf.len().saturating_sub(self.name_buf[self.name_buf.len() - 1] as usize)
 	}
 	fn set_name_len(&mut self, len: usize) {
 		self.name_buf[self.name_buf.len() - 1] = (self.name_buf.len() - len) as u8;
@@ -214,6 +275,10 @@ impl Descriptor {
 	pub fn is_file(&self) -> bool {
 		self.content_type != 0
 	}
+	/// Is this a whiteout file descriptor?
+	pub fn is_whiteout(&self) -> bool {
+		self.content_type == Descriptor::WHITEOUT
+	}
 }
 impl fmt::Debug for Descriptor {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
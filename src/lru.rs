@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+const NIL: usize = usize::MAX;
+
+struct Node<K, V> {
+	key: K,
+	value: V,
+	prev: usize,
+	next: usize,
+}
+
+/// Least recently used cache with a fixed capacity.
+///
+/// Entries are stored in a vector and linked in order of use, the least recently used entry is reused when full.
+pub(crate) struct Lru<K, V> {
+	map: HashMap<K, usize>,
+	nodes: Vec<Node<K, V>>,
+	// Most and least recently used entries
+	head: usize,
+	tail: usize,
+	capacity: usize,
+	pub hits: u64,
+	pub misses: u64,
+}
+impl<K: Copy + Eq + Hash, V> Lru<K, V> {
+	pub fn new(capacity: usize) -> Lru<K, V> {
+		Lru { map: HashMap::new(), nodes: Vec::new(), head: NIL, tail: NIL, capacity, hits: 0, misses: 0 }
+	}
+	pub fn capacity(&self) -> usize {
+		self.capacity
+	}
+	/// Changes the capacity, all entries are evicted.
+	pub fn set_capacity(&mut self, capacity: usize) {
+		self.clear();
+		self.capacity = capacity;
+	}
+	pub fn clear(&mut self) {
+		self.map.clear();
+		self.nodes.clear();
+		self.head = NIL;
+		self.tail = NIL;
+	}
+	fn unlink(&mut self, i: usize) {
+		let Node { prev, next, .. } = self.nodes[i];
+		if prev != NIL { self.nodes[prev].next = next; } else { self.head = next; }
+		if next != NIL { self.nodes[next].prev = prev; } else { self.tail = prev; }
+	}
+	fn push_front(&mut self, i: usize) {
+		self.nodes[i].prev = NIL;
+		self.nodes[i].next = self.head;
+		if self.head != NIL { self.nodes[self.head].prev = i; } else { self.tail = i; }
+		self.head = i;
+	}
+	/// Returns if an entry exists without marking it as used or counting as a hit or miss.
+	pub fn contains_key(&self, key: &K) -> bool {
+		self.map.contains_key(key)
+	}
+	/// Looks up an entry and marks it as most recently used.
+	pub fn get(&mut self, key: &K) -> Option<&V> {
+		match self.map.get(key) {
+			Some(&i) => {
+				self.hits += 1;
+				self.unlink(i);
+				self.push_front(i);
+				Some(&self.nodes[i].value)
+			},
+			None => {
+				self.misses += 1;
+				None
+			},
+		}
+	}
+	/// Inserts an entry evicting the least recently used entry if full.
+	pub fn insert(&mut self, key: K, value: V) {
+		if self.capacity == 0 {
+			return;
+		}
+		let i = if let Some(&i) = self.map.get(&key) {
+			self.nodes[i].value = value;
+			self.unlink(i);
+			i
+		}
+		else if self.nodes.len() < self.capacity {
+			self.nodes.push(Node { key, value, prev: NIL, next: NIL });
+			self.map.insert(key, self.nodes.len() - 1);
+			self.nodes.len() - 1
+		}
+		else {
+			let i = self.tail;
+			self.unlink(i);
+			self.map.remove(&self.nodes[i].key);
+			self.map.insert(key, i);
+			self.nodes[i].key = key;
+			self.nodes[i].value = value;
+			i
+		};
+		self.push_front(i);
+	}
+}
+
+#[test]
+fn test_lru() {
+	let mut lru = Lru::new(2);
+	lru.insert(1, "one");
+	lru.insert(2, "two");
+	assert_eq!(lru.get(&1), Some(&"one"));
+	// Evicts 2 as 1 was used more recently
+	lru.insert(3, "three");
+	assert_eq!(lru.get(&2), None);
+	assert_eq!(lru.get(&1), Some(&"one"));
+	assert_eq!(lru.get(&3), Some(&"three"));
+	assert_eq!((lru.hits, lru.misses), (3, 1));
+
+	lru.set_capacity(0);
+	lru.insert(1, "one");
+	assert_eq!(lru.get(&1), None);
+}
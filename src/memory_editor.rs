@@ -1,3 +1,6 @@
+use std::{borrow::Cow, cmp, fs, io, ptr};
+use std::io::{Seek, SeekFrom, Write};
+use std::collections::HashMap;
 use crate::*;
 
 /// PAK editor with memory buffers.
@@ -5,6 +8,11 @@ use crate::*;
 pub struct MemoryEditor {
 	blocks: Vec<Block>,
 	dir: Vec<Descriptor>,
+	sorted: bool,
+	path_index: bool,
+	case: directory::Case,
+	// Number of leading blocks already written to the PAK file opened with open_append, see finish_append
+	append_offset: Option<usize>,
 }
 impl MemoryEditor {
 	/// Creates a new `MemoryEditor` instance.
@@ -12,12 +20,17 @@ impl MemoryEditor {
 		// The blocks must contain at least space for the header ref$1
 		let blocks = vec![Block::default(); Header::BLOCKS_LEN];
 		let dir = Vec::new();
-		MemoryEditor { blocks, dir }
+		MemoryEditor { blocks, dir, sorted: false, path_index: false, case: directory::Case::Sensitive, append_offset: None }
 	}
 
 	/// Creates a new `MemoryEditor` instance from existing encrypted PAK file.
+	///
+	/// Sorted PAK files remain sorted when finished, see [`set_sorted`](#method.set_sorted).
+	/// PAK files with a path index keep it when finished, see [`set_path_index`](#method.set_path_index).
 	pub fn from_blocks(mut blocks: Vec<Block>, key: &Key) -> MemoryEditor {
 		let dir;
+		let mut sorted = false;
+		let mut has_path_index = false;
 		// The blocks must contain at least space for the header ref$1
 		if blocks.len() < Header::BLOCKS_LEN {
 			blocks.resize(Header::BLOCKS_LEN, Block::default());
@@ -27,47 +40,206 @@ impl MemoryEditor {
 			// Decrypt the header to find and decrypt the directory
 			let header = crypt::decrypt_header(unsafe { &*(blocks.as_ptr() as *const Header) }, key);
 			dir = crypt::decrypt_dir(&blocks, &header.directory, key);
+			sorted = header.is_sorted();
+			has_path_index = header.has_path_index();
+			let index_len = path_index::section(&blocks, &header, key).map_or(0, |index| index.len());
 			// Avoid creating extra garbage if the directory is at the end
-			if blocks.len() == header.directory.offset as usize + header.directory.size as usize * Descriptor::BLOCKS_LEN {
+			if blocks.len() == header.directory.offset as usize + header.directory.size as usize * Descriptor::BLOCKS_LEN + index_len {
 				blocks.truncate(header.directory.offset as usize);
 			}
 		}
-		MemoryEditor { blocks, dir }
+		MemoryEditor { blocks, dir, sorted, path_index: has_path_index, case: directory::Case::Sensitive, append_offset: None }
+	}
+
+	pub(crate) fn from_parts(blocks: Vec<Block>, dir: Vec<Descriptor>, sorted: bool, path_index: bool) -> MemoryEditor {
+		MemoryEditor { blocks, dir, sorted, path_index, case: directory::Case::Sensitive, append_offset: None }
+	}
+
+	/// Opens an existing PAK file for appending.
+	///
+	/// The existing blocks are kept as is, including the current directory which becomes stale when finished.
+	/// New file contents are allocated after the existing blocks, see [`finish_append`](#method.finish_append).
+	/// Any trailing blocks left behind by an interrupted append are ignored and overwritten.
+	///
+	/// Only newly allocated sections are written when finished.
+	/// Writers of existing files move their section to newly allocated blocks, see [`MemoryEditFile::writer`](struct.MemoryEditFile.html#method.writer).
+	/// Modifying the contents of existing sections in place, eg. with `copy_data` or `reencrypt_data`, is not persisted.
+	///
+	/// The whole PAK file is read into memory like [`read`](fn.read.html), appending requires as much memory as the PAK file itself.
+	/// Only the writes are incremental, use an [`IoReader`](struct.IoReader.html) to inspect large PAK files without loading them.
+	///
+	/// Returns an error converted from [`Error`](enum.Error.html) if the file does not encode a PAK file.
+	pub fn open_append(file: &mut fs::File, key: &Key) -> io::Result<MemoryEditor> {
+		file.seek(SeekFrom::Start(0))?;
+		let blocks = read(&mut *file, key)?;
+		let header = crypt::decrypt_header(unsafe { &*(blocks.as_ptr() as *const Header) }, key);
+		let dir = crypt::decrypt_dir(&blocks, &header.directory, key);
+		let append_offset = Some(blocks.len());
+		Ok(MemoryEditor { blocks, dir, sorted: header.is_sorted(), path_index: header.has_path_index(), case: directory::Case::Sensitive, append_offset })
+	}
+
+	/// Creates a new `MemoryEditor` instance from existing encrypted PAK file.
+	///
+	/// Unlike `from_blocks` the header is validated and any problems are reported.
+	pub fn try_from_blocks(blocks: Vec<Block>, key: &Key) -> Result<MemoryEditor> {
+		if blocks.len() < Header::BLOCKS_LEN {
+			return Err(Error::Truncated);
+		}
+		let header = crypt::decrypt_header(unsafe { &*(blocks.as_ptr() as *const Header) }, key);
+		error::check_info(&header, blocks.len())?;
+		Ok(MemoryEditor::from_blocks(blocks, key))
+	}
+
+	/// Salvages a damaged PAK file.
+	///
+	/// The header must be intact, see [`directory::repair`](directory/fn.repair.html) for how the directory is rebuilt.
+	/// File descriptors whose section is out of range or too small for their contents are dropped.
+	/// Returns the editor with the repaired directory.
+	/// The full path and descriptor of everything that was dropped is optionally appended to the dropped output argument.
+	pub fn repair(blocks: Vec<Block>, key: &Key, dropped: Option<&mut Vec<(Vec<u8>, Descriptor)>>) -> Result<MemoryEditor> {
+		let mut editor = MemoryEditor::try_from_blocks(blocks, key)?;
+		let blocks_len = editor.blocks.len();
+		let (dir, mut lost) = directory::repair(&editor.dir, |desc| {
+			desc.section.offset >= Header::BLOCKS_LEN as u32 &&
+			desc.section.offset as usize + desc.section.size as usize <= blocks_len &&
+			bytes2blocks(desc.content_size) <= desc.section.size
+		});
+		editor.dir = dir;
+		if let Some(dropped) = dropped {
+			dropped.append(&mut lost);
+		}
+		Ok(editor)
+	}
+
+	/// Sets whether `finish` writes the siblings of every directory sorted by name.
+	///
+	/// Sorted PAK files are flagged in the header and readers use this to speed up lookups.
+	pub fn set_sorted(&mut self, sorted: bool) {
+		self.sorted = sorted;
+	}
+
+	/// Sets whether `finish` writes a hashed path index after the directory.
+	///
+	/// Readers use the path index to find descriptors by their path in constant time.
+	pub fn set_path_index(&mut self, path_index: bool) {
+		self.path_index = path_index;
+	}
+
+	/// Sets the case sensitivity used by the `try_` methods to reject names.
+	///
+	/// Unless `Case::Sensitive` names which differ only by case from an existing name in the same directory are rejected with `CaseConflict`.
+	pub fn set_case(&mut self, case: directory::Case) {
+		self.case = case;
+	}
+
+	fn check_path<'a>(&self, path: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+		let path = path::normalize(path)?;
+		if self.case != directory::Case::Sensitive && directory::find_case_conflict(&self.dir, &path, self.case).is_some() {
+			return Err(Error::CaseConflict);
+		}
+		Ok(path)
 	}
 
 	/// Creates a file at the given path.
 	///
 	/// The file is assigned a content_type of `1`.
 	/// A new section is allocated and the contents are encrypted and written into the section.
+	///
+	/// The path is normalized, nothing is created if the path is invalid or conflicts with existing names.
+	/// Use [`try_create_file`](#method.try_create_file) to find out why.
 	pub fn create_file(&mut self, path: &[u8], content: &[u8], key: &Key) {
-		self.edit_file(path).set_content(1, content.len() as u32).allocate_data().init_data(content, key);
+		let _ = self.try_create_file(path, content, key);
+	}
+
+	/// Creates a file at the given path.
+	///
+	/// Unlike `create_file` returns `InvalidPath` if the path is invalid or `CaseConflict` if the path conflicts with existing names, see [`set_case`](#method.set_case).
+	pub fn try_create_file(&mut self, path: &[u8], content: &[u8], key: &Key) -> Result<()> {
+		self.try_edit_file(path)?.set_content(1, content.len() as u32).allocate_data().init_data(content, key);
+		Ok(())
 	}
 
 	/// Creates a symbolic link from the path to the given file descriptor.
+	///
+	/// The path is normalized, nothing is created if the path is invalid or conflicts with existing names.
 	pub fn create_symlink(&mut self, path: &[u8], file_desc: &Descriptor) {
-		self.edit_file(path).set_content(file_desc.content_type, file_desc.content_size).set_section(&file_desc.section);
+		if let Ok(mut file) = self.try_edit_file(path) {
+			file.set_content(file_desc.content_type, file_desc.content_size).set_section(&file_desc.section);
+		}
+	}
+
+	/// Creates a whiteout at the given path.
+	///
+	/// The whiteout hides the path in lower layers of an [`OverlayReader`](struct.OverlayReader.html).
+	/// The path is normalized, nothing is created if the path is invalid or conflicts with existing names.
+	pub fn create_whiteout(&mut self, path: &[u8]) {
+		if let Ok(mut file) = self.try_edit_file(path) {
+			file.set_content(Descriptor::WHITEOUT, 0).allocate_data();
+		}
 	}
 
 	/// Creates a file descriptor at the given path.
 	/// Any missing parent directories are automatically created.
+	///
+	/// # Panics
+	///
+	/// Panics if the path is invalid or conflicts with existing names, use [`try_edit_file`](#method.try_edit_file) to handle these errors.
 	pub fn edit_file(&mut self, path: &[u8]) -> MemoryEditFile<'_> {
-		let desc = directory::create(&mut self.dir, path);
+		match self.try_edit_file(path) {
+			Ok(file) => file,
+			Err(err) => panic!("edit_file {:?}: {}", String::from_utf8_lossy(path), err),
+		}
+	}
+
+	/// Creates a file descriptor at the given path.
+	///
+	/// Unlike `edit_file` returns `InvalidPath` if the path is invalid or `CaseConflict` if the path conflicts with existing names, see [`set_case`](#method.set_case).
+	pub fn try_edit_file(&mut self, path: &[u8]) -> Result<MemoryEditFile<'_>> {
+		let path = self.check_path(path)?;
+		let desc = directory::create(&mut self.dir, &path);
 		let blocks = &mut self.blocks;
-		MemoryEditFile { desc, blocks }
+		let append_offset = self.append_offset.unwrap_or(0);
+		Ok(MemoryEditFile { desc, blocks, append_offset })
+	}
+
+	/// Creates an empty file at the given path and returns a writer for its contents.
+	///
+	/// The file is assigned a content_type of `1`.
+	/// Its section grows as content is written and the descriptor is updated when the writer is finished or dropped.
+	///
+	/// # Panics
+	///
+	/// Panics if the path is invalid or conflicts with existing names, see [`edit_file`](#method.edit_file).
+	pub fn write_file(&mut self, path: &[u8], key: &Key) -> MemoryEditWriter<'_> {
+		let mut file = self.edit_file(path);
+		file.set_content(1, 0).allocate_data();
+		file.writer(key)
 	}
 
 	/// Creates a directory descriptor at the given path.
 	/// Any missing parent directories are automatically created.
+	///
+	/// The path is normalized, nothing is created if the path is invalid or conflicts with existing names.
+	/// Use [`try_create_dir`](#method.try_create_dir) to find out why.
 	pub fn create_dir(&mut self, path: &[u8]) {
-		let desc = directory::create(&mut self.dir, path);
+		let _ = self.try_create_dir(path);
+	}
+
+	/// Creates a directory descriptor at the given path.
+	///
+	/// Unlike `create_dir` returns `InvalidPath` if the path is invalid or `CaseConflict` if the path conflicts with existing names, see [`set_case`](#method.set_case).
+	pub fn try_create_dir(&mut self, path: &[u8]) -> Result<()> {
+		let path = self.check_path(path)?;
+		let desc = directory::create(&mut self.dir, &path);
 		desc.content_type = 0;
 		desc.content_size = 0;
 		desc.section = Section::default();
+		Ok(())
 	}
 
 	/// Removes a descriptor at the given path.
 	///
-	/// Returns `false` if no descriptor is found at the given path.
+	/// Returns `false` if the path is invalid or no descriptor is found at the given path.
 	/// The directory remains unchanged, the output argument deleted is untouched.
 	///
 	/// Returns `true` if a file descriptor is found at the given path.
@@ -76,22 +248,211 @@ impl MemoryEditor {
 	/// Returns `true` if a directory descriptor is found at the given path.
 	/// The descriptor is removed and optionally copied to the deleted output argument.
 	/// All the direct children of the removed directory are moved to its parent directory.
+	/// Use [`remove_all`](#method.remove_all) to remove the directory together with its children.
 	pub fn remove(&mut self, path: &[u8], deleted: Option<&mut Descriptor>) -> bool {
-		directory::remove(&mut self.dir, path, deleted)
+		match path::normalize(path) {
+			Ok(path) => directory::remove(&mut self.dir, &path, deleted),
+			Err(_) => false,
+		}
+	}
+
+	/// Removes a descriptor at the given path together with all its children.
+	///
+	/// Returns the removed descriptors, empty if the path is invalid or no descriptor is found at the given path.
+	/// The sections of removed files are freed by [`gc`](#method.gc).
+	pub fn remove_all(&mut self, path: &[u8]) -> Vec<Descriptor> {
+		match path::normalize(path) {
+			Ok(path) => directory::remove_all(&mut self.dir, &path),
+			Err(_) => Vec::new(),
+		}
+	}
+
+	/// Removes all the descriptors matching the glob pattern together with all their children.
+	///
+	/// See [`directory::glob`](directory/fn.glob.html) for the supported patterns.
+	/// The pattern is normalized like a path, nothing is removed if it is invalid.
+	/// Returns the removed descriptors, the sections of removed files are freed by [`gc`](#method.gc).
+	pub fn remove_glob(&mut self, pattern: &[u8]) -> Vec<Descriptor> {
+		match path::normalize(pattern) {
+			Ok(pattern) => directory::remove_glob(&mut self.dir, &pattern),
+			Err(_) => Vec::new(),
+		}
+	}
+
+	/// Renames a file or moves a directory together with its contents.
+	///
+	/// Any missing parent directories of the destination are created.
+	/// Only the descriptors are moved, the file contents are not re-encrypted.
+	///
+	/// Returns `NotFound` if no descriptor exists at `from` or `AlreadyExists` if a descriptor exists at `to`.
+	/// Returns `InvalidPath` if either path is invalid or `to` is inside `from`, and `CaseConflict` if `to` conflicts with existing names, see [`set_case`](#method.set_case).
+	pub fn rename(&mut self, from: &[u8], to: &[u8]) -> Result<()> {
+		let from = path::normalize(from)?;
+		let to = path::normalize(to)?;
+		let desc = directory::find_desc(&self.dir, &from).ok_or(Error::NotFound)?;
+		if directory::find_desc(&self.dir, &to).is_some() {
+			return Err(Error::AlreadyExists);
+		}
+		// Changing only the case of a name does not conflict with itself
+		if self.case != directory::Case::Sensitive {
+			if let Some(conflict) = directory::find_case_conflict(&self.dir, &to, self.case) {
+				if !ptr::eq(conflict, desc) {
+					return Err(Error::CaseConflict);
+				}
+			}
+		}
+		if !directory::rename(&mut self.dir, &from, &to) {
+			return Err(Error::InvalidPath);
+		}
+		Ok(())
+	}
+
+	/// Copies a file or a directory together with its contents.
+	///
+	/// Any missing parent directories of the destination are created.
+	/// The copied file descriptors share their sections with the original like [`create_symlink`](#method.create_symlink).
+	///
+	/// Returns `NotFound` if no descriptor exists at `from` or `AlreadyExists` if a descriptor exists at `to`.
+	/// Returns `InvalidPath` if either path is invalid and `CaseConflict` if `to` conflicts with existing names, see [`set_case`](#method.set_case).
+	pub fn copy(&mut self, from: &[u8], to: &[u8]) -> Result<()> {
+		let from = path::normalize(from)?;
+		let subtree = directory::find(&self.dir, &from).to_vec();
+		if subtree.is_empty() {
+			return Err(Error::NotFound);
+		}
+		let to = self.check_dest(to)?;
+		self.insert(&to, &subtree)
+	}
+
+	/// Imports a file or a directory together with its contents from another PAK file.
+	///
+	/// The file contents are copied into newly allocated sections, files sharing a section in the reader keep sharing their copied section.
+	/// If the reader uses the same key the ciphertext and nonces are copied as is, otherwise the contents are re-encrypted with the given key.
+	///
+	/// Returns `NotFound` if no descriptor exists at `from` in the reader or `AlreadyExists` if a descriptor exists at `to`.
+	/// Returns `OutOfRange` if any file or directory in the reader is not valid.
+	/// Returns `InvalidPath` if either path is invalid and `CaseConflict` if `to` conflicts with existing names, see [`set_case`](#method.set_case).
+	pub fn import(&mut self, reader: &MemoryReader, from: &[u8], to: &[u8], key: &Key) -> Result<()> {
+		let from = path::normalize(from)?;
+		let root = reader.find(&from).ok_or(Error::NotFound)?;
+		let mut subtree = vec![root];
+		if root.is_dir() {
+			if !reader.is_valid_dir(&root) {
+				return Err(Error::OutOfRange);
+			}
+			subtree.extend(root.section.range_usize().map(|i| reader.decrypt_desc(i)));
+		}
+		if !directory::is_nested(&subtree) || subtree.iter().any(|desc| desc.is_file() && !reader.is_valid_file(desc)) {
+			return Err(Error::OutOfRange);
+		}
+		let to = self.check_dest(to)?;
+		self.copy_sections(reader, &mut subtree, &mut HashMap::new(), key);
+		self.insert(&to, &subtree)
+	}
+
+	/// Merges all the files and directories of another PAK file.
+	///
+	/// The file contents are copied as with [`import`](#method.import).
+	/// Directories existing in both are merged, any other path existing in both is resolved by the given policy.
+	///
+	/// The sections of files replaced with [`Conflict::LastWins`](merge/enum.Conflict.html#variant.LastWins) are freed by [`gc`](#method.gc).
+	///
+	/// Returns `AlreadyExists` for the first path existing in both with `Conflict::Error`, the PAK file is partially merged.
+	/// Returns `OutOfRange` if any file in the reader is not valid or `InvalidPath` if any name in the reader is not valid.
+	/// Returns `CaseConflict` if a path conflicts with existing names, see [`set_case`](#method.set_case).
+	pub fn merge(&mut self, reader: &MemoryReader, key: &Key, conflict: merge::Conflict) -> Result<()> {
+		let mut sections = HashMap::new();
+		// Directory whose children are skipped
+		let mut skipped: Option<Vec<u8>> = None;
+		for (path, desc) in reader.walk() {
+			let path = self.check_path(&path)?.into_owned();
+			if let Some(skipped_path) = &skipped {
+				if path.starts_with(skipped_path) && path.get(skipped_path.len()) == Some(&b'/') {
+					continue;
+				}
+				skipped = None;
+			}
+			if let Some(existing) = directory::find_desc(&self.dir, &path) {
+				if existing.is_dir() && desc.is_dir() {
+					continue;
+				}
+				match conflict {
+					merge::Conflict::LastWins => {
+						self.remove_all(&path);
+					},
+					merge::Conflict::FirstWins => {
+						skipped = Some(path);
+						continue;
+					},
+					merge::Conflict::Error => return Err(Error::AlreadyExists),
+				}
+			}
+			if desc.is_dir() {
+				self.create_dir(&path);
+			}
+			else {
+				if !reader.is_valid_file(&desc) {
+					return Err(Error::OutOfRange);
+				}
+				let mut file = [desc];
+				self.copy_sections(reader, &mut file, &mut sections, key);
+				self.insert(&path, &file)?;
+			}
+		}
+		Ok(())
+	}
+
+	// Copies the file contents from the reader, sections shared in the reader remain shared
+	fn copy_sections(&mut self, reader: &MemoryReader, subtree: &mut [Descriptor], sections: &mut HashMap<Section, Section>, key: &Key) {
+		for desc in subtree.iter_mut().filter(|desc| desc.is_file()) {
+			let blocks = &mut self.blocks;
+			desc.section = *sections.entry(desc.section).or_insert_with(|| {
+				let mut section = desc.section;
+				section.offset = blocks.len() as u32;
+				blocks.extend_from_slice(&reader.blocks()[desc.section.range_usize()]);
+				if reader.key() != key {
+					crypt::random(slice::from_mut(&mut section.nonce));
+					crypt::reencrypt_data(&mut blocks[section.range_usize()], &desc.section.nonce, &section.nonce, reader.key(), key);
+				}
+				section
+			});
+		}
+	}
+
+	fn check_dest<'a>(&self, to: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+		let to = self.check_path(to)?;
+		if directory::find_desc(&self.dir, &to).is_some() {
+			return Err(Error::AlreadyExists);
+		}
+		Ok(to)
+	}
+	fn insert(&mut self, to: &[u8], subtree: &[Descriptor]) -> Result<()> {
+		if !directory::insert(&mut self.dir, to, subtree) {
+			return Err(Error::InvalidPath);
+		}
+		Ok(())
 	}
 
 	/// Compacts the referenced blocks from file descriptors.
 	///
 	/// Any file descriptors with an invalid section address have their address zeroed.
+	/// File descriptors sharing the same section, eg. symbolic links, keep sharing the compacted section.
+	///
+	/// All blocks are moved, a PAK file opened with `open_append` can no longer be finished with `finish_append` but must be rewritten with `finish`.
 	pub fn gc(&mut self) {
 		let mut blocks = vec![Block::default(); Header::BLOCKS_LEN];
+		let mut moved = HashMap::new();
 
 		for desc in &mut self.dir {
 			if desc.is_file() {
-				let offset = blocks.len();
-				if let Some(contents) = self.blocks.get(desc.section.range_usize()) {
+				if let Some(&offset) = moved.get(&(desc.section.offset, desc.section.size)) {
+					desc.section.offset = offset;
+				}
+				else if let Some(contents) = self.blocks.get(desc.section.range_usize()) {
+					let offset = blocks.len() as u32;
 					blocks.extend_from_slice(contents);
-					desc.section.offset = offset as u32;
+					moved.insert((desc.section.offset, desc.section.size), offset);
+					desc.section.offset = offset;
 				}
 				else {
 					// Not much to do when we find an invalid descriptor...
@@ -101,6 +462,7 @@ impl MemoryEditor {
 		}
 
 		self.blocks = blocks;
+		self.append_offset = None;
 	}
 
 	/// Finish editing the PAK file.
@@ -108,9 +470,12 @@ impl MemoryEditor {
 	/// Initializes the header, encrypts the directory and appends it to the blocks.
 	/// Returns the encrypted PAK file and the unencrypted directory for inspection.
 	pub fn finish(self, key: &Key) -> (Vec<Block>, Vec<Descriptor>) {
-		let MemoryEditor { mut blocks, mut dir } = self;
+		let MemoryEditor { mut blocks, mut dir, sorted, path_index, .. } = self;
 
 		// Finalize the directory
+		if sorted {
+			directory::sort(&mut dir);
+		}
 		directory::update_dir_address(&mut dir);
 
 		// Initialize the header and pick random iv and nonce
@@ -121,7 +486,13 @@ impl MemoryEditor {
 			let header_mut = unsafe { &mut *(blocks.as_mut_ptr() as *mut Header) };
 			crypt::random(header_mut.as_mut());
 			header_mut.info.version = InfoHeader::VERSION;
-			header_mut.info.unused = [0];
+			header_mut.info.flags = 0;
+			if sorted {
+				header_mut.info.flags |= InfoHeader::FLAG_SORTED;
+			}
+			if path_index {
+				header_mut.info.flags |= InfoHeader::FLAG_PATH_INDEX;
+			}
 
 			// Calculate offset for the directory
 			header_mut.info.directory.offset = blocks.len() as u32;
@@ -138,9 +509,51 @@ impl MemoryEditor {
 			});
 		}
 
+		// Append the path index encrypted as a continuation of the directory
+		if path_index {
+			let mut index = path_index::build(&dir);
+			crypt::crypt_inplace(&mut index, &path_index::nonce(&directory), key);
+			blocks.extend_from_slice(&index);
+		}
+
 		// Return the produced PAK file
 		(blocks, dir)
 	}
+
+	/// Finish editing the PAK file opened with [`open_append`](#method.open_append).
+	///
+	/// Writes the new file contents and the new directory after the existing blocks and syncs them to disk.
+	/// The existing blocks held in memory since `open_append` are not written again.
+	/// Only then the header is rewritten to reference the new directory, the old file contents are never overwritten.
+	/// If interrupted the PAK file remains readable with either the old or the new directory, see [`read`](fn.read.html) for the layout.
+	/// Returns the unencrypted directory for inspection.
+	///
+	/// Returns an error of kind `InvalidInput` if the PAK file was not opened with `open_append` or was compacted with [`gc`](#method.gc).
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// let key = &[13, 42];
+	/// let mut file = std::fs::OpenOptions::new().read(true).write(true).open("FILE.PAK")?;
+	/// let mut edit = pak::MemoryEditor::open_append(&mut file, key)?;
+	/// edit.create_file(b"saves/slot1", b"progress", key);
+	/// edit.finish_append(&mut file, key)?;
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	pub fn finish_append(self, file: &mut fs::File, key: &Key) -> io::Result<Vec<Descriptor>> {
+		// Writing compacted blocks over the old blocks is not crash tolerant
+		let append_offset = self.append_offset.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "PAK file not opened for appending"))?;
+		let (blocks, dir) = self.finish(key);
+		file.seek(SeekFrom::Start((append_offset * BLOCK_SIZE) as u64))?;
+		file.write_all(blocks[append_offset..].as_bytes())?;
+		file.set_len((blocks.len() * BLOCK_SIZE) as u64)?;
+		file.sync_data()?;
+		// Commit the new directory by rewriting the header last
+		file.seek(SeekFrom::Start(0))?;
+		file.write_all(blocks[..Header::BLOCKS_LEN].as_bytes())?;
+		file.sync_data()?;
+		Ok(dir)
+	}
 }
 
 /// Memory file editor.
@@ -150,6 +563,8 @@ impl MemoryEditor {
 pub struct MemoryEditFile<'a> {
 	desc: &'a mut Descriptor,
 	blocks: &'a mut Vec<Block>,
+	// Sections before this offset are already written to a PAK file opened for appending
+	append_offset: usize,
 }
 impl<'a> MemoryEditFile<'a> {
 	/// Sets the content type and size for this file descriptor.
@@ -209,6 +624,12 @@ impl<'a> MemoryEditFile<'a> {
 
 		return self;
 	}
+	fn try_section_mut(&mut self) -> Result<&mut [Block]> {
+		if self.desc.section.offset < Header::BLOCKS_LEN as u32 {
+			return Err(Error::OutOfRange);
+		}
+		self.blocks.get_mut(self.desc.section.range_usize()).ok_or(Error::OutOfRange)
+	}
 	/// Copies and encrypts the content with the given key into the address specified by this file descriptor.
 	///
 	/// # Panics
@@ -220,6 +641,18 @@ impl<'a> MemoryEditFile<'a> {
 		crypt::encrypt_data(blocks, &self.desc.section.nonce, key, 0, content, crypt::Pad::Zero);
 		return self;
 	}
+	/// Copies and encrypts the content with the given key into the address specified by this file descriptor.
+	///
+	/// Returns `OutOfRange` if the section is invalid or too small for the content.
+	pub fn try_init_data(&mut self, content: &[u8], key: &Key) -> Result<&mut MemoryEditFile<'a>> {
+		let nonce = self.desc.section.nonce;
+		let blocks = self.try_section_mut()?;
+		if content.len() > blocks.len() * BLOCK_SIZE {
+			return Err(Error::OutOfRange);
+		}
+		crypt::encrypt_data(blocks, &nonce, key, 0, content, crypt::Pad::Zero);
+		Ok(self)
+	}
 	/// Initialize the contents with zeroes.
 	///
 	/// # Panics
@@ -231,6 +664,15 @@ impl<'a> MemoryEditFile<'a> {
 		crypt::encrypt_zero(blocks, &self.desc.section.nonce, key);
 		return self;
 	}
+	/// Initialize the contents with zeroes.
+	///
+	/// Returns `OutOfRange` if the section is invalid.
+	pub fn try_zero_data(&mut self, key: &Key) -> Result<&mut MemoryEditFile<'a>> {
+		let nonce = self.desc.section.nonce;
+		let blocks = self.try_section_mut()?;
+		crypt::encrypt_zero(blocks, &nonce, key);
+		Ok(self)
+	}
 	/// Copies and encrypts content to a subsection of the file.
 	///
 	/// The file must be initialized (either through `init_data` or `zero_data`) before it can be updated.
@@ -244,6 +686,46 @@ impl<'a> MemoryEditFile<'a> {
 		crypt::encrypt_data(blocks, &self.desc.section.nonce, key, byte_offset, content, crypt::Pad::Transparent);
 		return self;
 	}
+	/// Copies and encrypts content to a subsection of the file.
+	///
+	/// Returns `OutOfRange` if the section is invalid or the content does not fit in the section at the given offset.
+	pub fn try_copy_data(&mut self, byte_offset: usize, content: &[u8], key: &Key) -> Result<&mut MemoryEditFile<'a>> {
+		let nonce = self.desc.section.nonce;
+		let blocks = self.try_section_mut()?;
+		if byte_offset.checked_add(content.len()).is_none_or(|end| end > blocks.len() * BLOCK_SIZE) {
+			return Err(Error::OutOfRange);
+		}
+		crypt::encrypt_data(blocks, &nonce, key, byte_offset, content, crypt::Pad::Transparent);
+		Ok(self)
+	}
+	/// Returns a writer for the contents of this file.
+	///
+	/// Writes start at the beginning of the existing content and grow the file as needed.
+	/// If the file has no valid section an empty one is allocated.
+	///
+	/// Writing into a section shared with other descriptors modifies their contents as well.
+	/// For a PAK file opened with [`open_append`](struct.MemoryEditor.html#method.open_append) an existing section is first copied to newly allocated blocks with a new nonce.
+	pub fn writer(self, key: &Key) -> MemoryEditWriter<'a> {
+		let MemoryEditFile { desc, blocks, append_offset } = self;
+		desc.content_type = u32::max(1, desc.content_type);
+		let valid = desc.section.offset >= Header::BLOCKS_LEN as u32 && blocks.get(desc.section.range_usize()).is_some();
+		if !valid {
+			desc.content_size = 0;
+			desc.section = Section { offset: blocks.len() as u32, size: 0, nonce: Block::default() };
+			crypt::random(slice::from_mut(&mut desc.section.nonce));
+		}
+		else if (desc.section.offset as usize) < append_offset {
+			// Copy on write, the old blocks are not written again when appending
+			let old = desc.section;
+			desc.section.offset = blocks.len() as u32;
+			crypt::random(slice::from_mut(&mut desc.section.nonce));
+			blocks.extend_from_within(old.range_usize());
+			crypt::reencrypt_data(&mut blocks[desc.section.range_usize()], &old.nonce, &desc.section.nonce, key, key);
+		}
+		let size = cmp::min(desc.content_size as u64, desc.section.size as u64 * BLOCK_SIZE as u64);
+		let section = desc.section;
+		MemoryEditWriter { desc, blocks, key: *key, section, size, pos: 0 }
+	}
 	/// Reencrypts the content.
 	///
 	/// The file must be initialized (either through `init_data` or `zero_data`) before it can be updated.
@@ -257,4 +739,456 @@ impl<'a> MemoryEditFile<'a> {
 		crypt::random(slice::from_mut(&mut self.desc.section.nonce));
 		crypt::reencrypt_data(blocks, &old_nonce, &self.desc.section.nonce, old_key, new_key);
 	}
+	/// Reencrypts the content.
+	///
+	/// Returns `OutOfRange` if the section is invalid.
+	pub fn try_reencrypt_data(&mut self, old_key: &Key, new_key: &Key) -> Result<()> {
+		self.try_section_mut()?;
+		self.reencrypt_data(old_key, new_key);
+		Ok(())
+	}
+}
+
+/// Memory file writer.
+///
+/// Implements `Write` and `Seek` for the contents of a file, see [`MemoryEditor::write_file`](struct.MemoryEditor.html#method.write_file).
+///
+/// The file's section grows as content is written past its end, moving it to the end of the blocks if necessary.
+/// Seeking past the end and writing fills the gap with zeroes.
+/// The file descriptor's content size and section are updated when flushed, finished or dropped.
+pub struct MemoryEditWriter<'a> {
+	desc: &'a mut Descriptor,
+	blocks: &'a mut Vec<Block>,
+	key: Key,
+	section: Section,
+	size: u64,
+	pos: u64,
+}
+impl<'a> MemoryEditWriter<'a> {
+	/// Returns the size of the file in bytes.
+	#[inline]
+	pub fn len(&self) -> u64 {
+		self.size
+	}
+	/// Returns if the file is empty.
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.size == 0
+	}
+	/// Returns the current position in the file.
+	#[inline]
+	pub fn position(&self) -> u64 {
+		self.pos
+	}
+	/// Finish writing and returns the updated file descriptor.
+	pub fn finish(mut self) -> Descriptor {
+		self.update_desc();
+		*self.desc
+	}
+	fn update_desc(&mut self) {
+		self.desc.content_size = self.size as u32;
+		self.desc.section = self.section;
+	}
+	// Ensures the section has room for the given number of bytes
+	fn reserve(&mut self, byte_size: u64) -> io::Result<()> {
+		if byte_size > u32::MAX as u64 {
+			return Err(io::Error::other("file too large"));
+		}
+		let new_size = bytes2blocks(byte_size as u32);
+		let old_size = self.section.size;
+		if new_size <= old_size {
+			return Ok(());
+		}
+		// Move the section to the end of the blocks so it can grow in place
+		if self.section.offset as usize + old_size as usize != self.blocks.len() {
+			let offset = self.blocks.len();
+			self.blocks.extend_from_within(self.section.range_usize());
+			self.section.offset = offset as u32;
+		}
+		// Append new blocks initialized with encrypted zeroes
+		let start = self.blocks.len();
+		self.blocks.resize(start + (new_size - old_size) as usize, Block::default());
+		crypt::encrypt_zero(&mut self.blocks[start..], &crypt::counter(&self.section.nonce, old_size as usize), &self.key);
+		self.section.size = new_size;
+		Ok(())
+	}
+}
+impl<'a> io::Write for MemoryEditWriter<'a> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		if buf.is_empty() {
+			return Ok(0);
+		}
+		let end = self.pos + buf.len() as u64;
+		let old_end = self.section.size as u64 * BLOCK_SIZE as u64;
+		self.reserve(end)?;
+		let blocks = &mut self.blocks[self.section.range_usize()];
+		// Zero the gap when writing past the end of the file
+		// The blocks appended by reserve are already zeroed but the existing section may contain stale contents
+		if self.pos > self.size {
+			let zeroes = [0u8; 256];
+			let gap_end = cmp::min(self.pos, old_end);
+			let mut offset = self.size;
+			while offset < gap_end {
+				let len = cmp::min(gap_end - offset, zeroes.len() as u64) as usize;
+				crypt::encrypt_data(blocks, &self.section.nonce, &self.key, offset as usize, &zeroes[..len], crypt::Pad::Transparent);
+				offset += len as u64;
+			}
+		}
+		crypt::encrypt_data(blocks, &self.section.nonce, &self.key, self.pos as usize, buf, crypt::Pad::Transparent);
+		self.pos = end;
+		self.size = cmp::max(self.size, end);
+		Ok(buf.len())
+	}
+	fn flush(&mut self) -> io::Result<()> {
+		self.update_desc();
+		Ok(())
+	}
+}
+impl<'a> io::Seek for MemoryEditWriter<'a> {
+	fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+		self.pos = file_reader::seek_pos(self.pos, self.size, pos)?;
+		Ok(self.pos)
+	}
+}
+impl<'a> Drop for MemoryEditWriter<'a> {
+	fn drop(&mut self) {
+		self.update_desc();
+	}
+}
+
+#[test]
+fn test_write_seek() {
+	use std::io::{Seek, SeekFrom, Write};
+
+	let key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	edit.create_file(b"before", b"blocking", key);
+	{
+		let mut writer = edit.write_file(b"sub/file", key);
+		writer.write_all(b"Hello").unwrap();
+		writer.write_all(b", world!").unwrap();
+		writer.seek(SeekFrom::Start(7)).unwrap();
+		writer.write_all(b"W").unwrap();
+		writer.seek(SeekFrom::End(3)).unwrap();
+		writer.write_all(b"!").unwrap();
+	}
+	edit.create_file(b"after", b"blocking", key);
+	{
+		// Growing an existing file moves its section to the end
+		let mut writer = edit.edit_file(b"before").writer(key);
+		writer.seek(SeekFrom::End(0)).unwrap();
+		writer.write_all(&[0x42; 40]).unwrap();
+		writer.finish();
+	}
+
+	let (blocks, _) = edit.finish(key);
+	let reader = MemoryReader::from_blocks(&blocks, key);
+	let file = reader.find(b"sub/file").unwrap();
+	assert_eq!(reader.read_data(&file), b"Hello, World!\0\0\0!");
+	let before = reader.find(b"before").unwrap();
+	let mut expected = b"blocking".to_vec();
+	expected.extend_from_slice(&[0x42; 40]);
+	assert_eq!(reader.read_data(&before), expected);
+	let after = reader.find(b"after").unwrap();
+	assert_eq!(reader.read_data(&after), b"blocking");
+
+	// Seeking past the end zeroes the stale contents of the existing section
+	let mut edit = MemoryEditor::new();
+	edit.edit_file(b"slack").set_content(1, 600).allocate_data().init_data(&[0xFF; 600], key).set_content(1, 1);
+	{
+		let mut writer = edit.edit_file(b"slack").writer(key);
+		writer.seek(SeekFrom::Start(500)).unwrap();
+		writer.write_all(b"!").unwrap();
+	}
+	let (blocks, _) = edit.finish(key);
+	let reader = MemoryReader::from_blocks(&blocks, key);
+	let mut expected = vec![0; 501];
+	expected[0] = 0xFF;
+	expected[500] = b'!';
+	assert_eq!(reader.read_data(&reader.find(b"slack").unwrap()), expected);
+}
+
+#[test]
+fn test_edit_errors() {
+	let key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	let mut file = edit.edit_file(b"file");
+	assert_eq!(file.try_init_data(b"content", key).err(), Some(Error::OutOfRange));
+	file.set_content(1, 7).allocate_data();
+	assert!(file.try_init_data(b"content", key).is_ok());
+	assert!(file.try_copy_data(8, b"12345678", key).is_ok());
+	assert_eq!(file.try_copy_data(9, b"12345678", key).err(), Some(Error::OutOfRange));
+	assert_eq!(file.try_init_data(&[0; 17], key).err(), Some(Error::OutOfRange));
+	let (blocks, _) = edit.finish(key);
+
+	assert_eq!(MemoryEditor::try_from_blocks(blocks.clone(), &[42, 13]).err(), Some(Error::BadKey));
+	let edit = MemoryEditor::try_from_blocks(blocks, key).unwrap();
+	let (blocks, _) = edit.finish(key);
+	let reader = MemoryReader::from_blocks(&blocks, key);
+	assert_eq!(reader.try_read_data(&reader.try_find(b"file").unwrap()).unwrap(), b"content");
+}
+
+#[test]
+fn test_repair() {
+	let key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	edit.create_file(b"a/b/file", b"content", key);
+	edit.create_file(b"a/lost", b"content", key);
+	edit.create_file(b"a/other", b"content", key);
+	edit.create_file(b"c/file", b"content", key);
+	let (mut blocks, _) = edit.finish(key);
+
+	// Corrupt the directory: a child count running past its parent, an out of range section and a bad name
+	verify::rewrite_dir(&mut blocks, key, |dir| {
+		dir[0].content_size = 100;
+		dir[3].section.offset = 10000;
+		dir[5].set_name(b"c/d");
+	});
+	assert!(!verify::verify(&blocks, key).is_ok());
+
+	let mut dropped = Vec::new();
+	let edit = MemoryEditor::repair(blocks, key, Some(&mut dropped)).unwrap();
+	let dropped: Vec<_> = dropped.iter().map(|(path, _)| &path[..]).collect();
+	assert_eq!(dropped, [&b"a/lost"[..], b"a/c/d", b"a/c/d/file"]);
+
+	let (blocks, _) = edit.finish(key);
+	assert!(verify::verify(&blocks, key).is_ok());
+	let reader = MemoryReader::from_blocks(&blocks, key);
+	assert_eq!(reader.read_data(&reader.find(b"a/b/file").unwrap()), b"content");
+	assert_eq!(reader.read_data(&reader.find(b"a/other").unwrap()), b"content");
+	assert!(reader.find(b"a/lost").is_none());
+}
+
+#[test]
+fn test_case_conflict() {
+	let key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	edit.set_case(directory::Case::Ascii);
+	edit.try_create_file(b"Data/Sound.ogg", b"a", key).unwrap();
+	edit.try_create_file(b"Data/sound.wav", b"b", key).unwrap();
+	assert_eq!(edit.try_create_file(b"data/other", b"c", key).err(), Some(Error::CaseConflict));
+	assert_eq!(edit.try_create_dir(b"Data/SOUND.ogg").err(), Some(Error::CaseConflict));
+	assert!(edit.try_edit_file(b"Data/Sound.ogg").is_ok());
+
+	// Byte-wise distinct names are allowed when case sensitive
+	edit.set_case(directory::Case::Sensitive);
+	edit.try_create_file(b"data/other", b"c", key).unwrap();
+}
+
+#[test]
+fn test_invalid_path() {
+	let key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	edit.try_create_file(b"/a//b\\.\\c/../file", b"content", key).unwrap();
+	edit.try_create_dir(b"a/./dir/").unwrap();
+	for path in [&b""[..], b"..", b"a/../../b", b"a/\0", b"a/./..", b"0123456789012345678901234567890123456789"] {
+		assert_eq!(edit.try_create_file(path, b"x", key).err(), Some(Error::InvalidPath));
+		assert_eq!(edit.try_create_dir(path).err(), Some(Error::InvalidPath));
+	}
+	assert!(!edit.remove(b"../a", None));
+	let (blocks, dir) = edit.finish(key);
+
+	// No unnamed descriptors are created
+	let paths: Vec<Vec<u8>> = directory::walk(&dir).map(|(path, _)| path).collect();
+	assert_eq!(paths, [&b"a"[..], b"a/b", b"a/b/file", b"a/dir"]);
+
+	let reader = MemoryReader::from_blocks(&blocks, key);
+	assert_eq!(reader.read_data(&reader.find(b"a\\b//file").unwrap()), b"content");
+	assert_eq!(reader.try_find(b"a/b/..").unwrap(), reader.find(b"a").unwrap());
+	assert_eq!(reader.try_find(b"a/\0").err(), Some(Error::InvalidPath));
+
+	// The infallible methods normalize the path and ignore invalid paths
+	let mut edit = MemoryEditor::new();
+	edit.create_file(b"0123456789012345678901234567890123456789", b"long", key);
+	edit.create_dir(b"a/./b");
+	edit.create_dir(b"c/./d");
+	edit.create_whiteout(b"a//w");
+	edit.create_whiteout(b"../w");
+	edit.create_file(b"x/../f", b"f", key);
+	assert!(edit.remove(b"c/./d", None));
+	assert_eq!(edit.remove_glob(b"a//?").len(), 2);
+	assert!(edit.remove_glob(b"..").is_empty());
+	let (_, dir) = edit.finish(key);
+	let paths: Vec<Vec<u8>> = directory::walk(&dir).map(|(path, _)| path).collect();
+	assert_eq!(paths, [&b"a"[..], b"c", b"f"]);
+}
+
+#[test]
+fn test_rename() {
+	let key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	edit.create_file(b"a/b/one", b"one", key);
+	edit.create_file(b"a/b/two", b"two", key);
+	edit.create_file(b"a/three", b"three", key);
+	edit.create_file(b"four", b"four", key);
+
+	edit.rename(b"a/b", b"c/d/b2").unwrap();
+	edit.rename(b"four", b"a/4").unwrap();
+	edit.rename(b"a/three", b"three").unwrap();
+	assert_eq!(edit.rename(b"missing", b"x").err(), Some(Error::NotFound));
+	assert_eq!(edit.rename(b"three", b"a/4").err(), Some(Error::AlreadyExists));
+	assert_eq!(edit.rename(b"c", b"c/d/e").err(), Some(Error::InvalidPath));
+	assert_eq!(edit.rename(b"c", b"../e").err(), Some(Error::InvalidPath));
+
+	edit.set_case(directory::Case::Ascii);
+	assert_eq!(edit.rename(b"three", b"C").err(), Some(Error::CaseConflict));
+	edit.rename(b"three", b"THREE").unwrap();
+
+	let (blocks, dir) = edit.finish(key);
+	let paths: Vec<Vec<u8>> = directory::walk(&dir).map(|(path, _)| path).collect();
+	assert_eq!(paths, [&b"a"[..], b"a/4", b"c", b"c/d", b"c/d/b2", b"c/d/b2/one", b"c/d/b2/two", b"THREE"]);
+	assert_eq!(directory::find_desc(&dir, b"c").unwrap().content_size, 4);
+
+	let reader = MemoryReader::from_blocks(&blocks, key);
+	for (path, content) in [(&b"c/d/b2/one"[..], &b"one"[..]), (b"c/d/b2/two", b"two"), (b"a/4", b"four"), (b"THREE", b"three")] {
+		assert_eq!(reader.read_data(&reader.find(path).unwrap()), content);
+	}
+}
+
+#[test]
+fn test_remove_all() {
+	let key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	edit.create_file(b"mods/a/one.txt", b"one", key);
+	edit.create_file(b"mods/a/sub/two.png", b"two", key);
+	edit.create_file(b"mods/b/three.png", b"three", key);
+	edit.create_file(b"readme.txt", b"readme", key);
+
+	let removed = edit.remove_all(b"mods/a");
+	let names: Vec<&[u8]> = removed.iter().map(|desc| desc.name()).collect();
+	assert_eq!(names, [&b"a"[..], b"one.txt", b"sub", b"two.png"]);
+	assert!(edit.remove_all(b"mods/a").is_empty());
+	assert!(edit.remove_all(b"..").is_empty());
+
+	edit.create_file(b"mods/c/four.png", b"four", key);
+	let removed = edit.remove_glob(b"mods/*/*.png");
+	assert_eq!(removed.len(), 2);
+	assert!(edit.remove_glob(b"").is_empty());
+
+	// Symbolic links keep sharing the section of their file
+	let readme = *directory::find_desc(&edit.dir, b"readme.txt").unwrap();
+	edit.create_symlink(b"readme.link", &readme);
+	edit.gc();
+	assert_eq!(edit.blocks.len(), Header::BLOCKS_LEN + 1);
+	assert_eq!(directory::find_desc(&edit.dir, b"readme.link").unwrap().section, directory::find_desc(&edit.dir, b"readme.txt").unwrap().section);
+	edit.remove(b"readme.link", None);
+	let (blocks, dir) = edit.finish(key);
+	let paths: Vec<Vec<u8>> = directory::walk(&dir).map(|(path, _)| path).collect();
+	assert_eq!(paths, [&b"mods"[..], b"mods/b", b"mods/c", b"readme.txt"]);
+	assert_eq!(dir[0].content_size, 2);
+	let reader = MemoryReader::from_blocks(&blocks, key);
+	assert_eq!(reader.read_data(&reader.find(b"readme.txt").unwrap()), b"readme");
+}
+
+#[test]
+fn test_copy_import() {
+	let key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	edit.create_file(b"a/one", b"one", key);
+	edit.create_file(b"a/sub/two", b"two", key);
+	let one = *directory::find_desc(&edit.dir, b"a/one").unwrap();
+	edit.create_symlink(b"a/link", &one);
+
+	edit.copy(b"a", b"b/a2").unwrap();
+	edit.copy(b"a/one", b"a/sub/one").unwrap();
+	assert_eq!(edit.copy(b"missing", b"c").err(), Some(Error::NotFound));
+	assert_eq!(edit.copy(b"a", b"b/a2").err(), Some(Error::AlreadyExists));
+	assert_eq!(directory::find_desc(&edit.dir, b"b/a2/one").unwrap().section, one.section);
+	assert_eq!(directory::find_desc(&edit.dir, b"b").unwrap().content_size, 5);
+	let (src_blocks, _) = edit.finish(key);
+	let reader = MemoryReader::from_blocks(&src_blocks, key);
+
+	for dest_key in [key, &[1, 2]] {
+		let mut edit = MemoryEditor::new();
+		edit.create_file(b"readme", b"readme", dest_key);
+		edit.import(&reader, b"b/a2", b"mods/a", dest_key).unwrap();
+		assert_eq!(edit.import(&reader, b"missing", b"x", dest_key).err(), Some(Error::NotFound));
+		assert_eq!(edit.import(&reader, b"a", b"readme", dest_key).err(), Some(Error::AlreadyExists));
+
+		// Shared sections remain shared, nonces are only kept when the keys are the same
+		let one = *directory::find_desc(&edit.dir, b"mods/a/one").unwrap();
+		assert_eq!(directory::find_desc(&edit.dir, b"mods/a/link").unwrap().section, one.section);
+		assert_eq!(one.section.nonce == reader.find(b"a/one").unwrap().section.nonce, dest_key == key);
+
+		let (blocks, _) = edit.finish(dest_key);
+		let imported = MemoryReader::from_blocks(&blocks, dest_key);
+		for (path, content) in [(&b"mods/a/one"[..], &b"one"[..]), (b"mods/a/link", b"one"), (b"mods/a/sub/two", b"two"), (b"readme", b"readme")] {
+			assert_eq!(imported.read_data(&imported.find(path).unwrap()), content);
+		}
+	}
+}
+
+#[test]
+fn test_append() {
+	let key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	edit.set_path_index(true);
+	edit.create_file(b"a", b"old", key);
+	edit.create_file(b"b", b"old", key);
+	let (blocks, _) = edit.finish(key);
+
+	let path = std::env::temp_dir().join(format!("pak-append-{}.pak", std::process::id()));
+	fs::write(&path, blocks.as_bytes()).unwrap();
+	let mut file = fs::OpenOptions::new().read(true).write(true).open(&path).unwrap();
+
+	let mut edit = MemoryEditor::open_append(&mut file, key).unwrap();
+	edit.create_file(b"a", b"new", key);
+	edit.remove(b"b", None);
+	edit.create_file(b"c", b"new", key);
+	edit.finish_append(&mut file, key).unwrap();
+
+	// The old blocks are untouched except for the header
+	let bytes = fs::read(&path).unwrap();
+	let old_bytes = blocks.as_bytes();
+	assert_eq!(&bytes[Header::BLOCKS_LEN * BLOCK_SIZE..old_bytes.len()], &old_bytes[Header::BLOCKS_LEN * BLOCK_SIZE..]);
+
+	let check = |blocks: &[Block], files: &[(&[u8], Option<&[u8]>)]| {
+		let reader = MemoryReader::try_from_blocks(blocks, key).unwrap();
+		for &(path, content) in files {
+			assert_eq!(reader.find(path).map(|desc| reader.read_data(&desc)).as_deref(), content);
+		}
+	};
+	let new_blocks = read(&bytes[..], key).unwrap();
+	assert_eq!(new_blocks.len() * BLOCK_SIZE, bytes.len());
+	check(&new_blocks, &[(b"a", Some(b"new")), (b"b", None), (b"c", Some(b"new"))]);
+
+	// An interrupted append leaves trailing blocks which are ignored and overwritten by the next append
+	let mut edit = MemoryEditor::open_append(&mut file, key).unwrap();
+	edit.create_file(b"d", &[1; 100], key);
+	let (interrupted, _) = edit.finish(key);
+	file.seek(SeekFrom::Start(bytes.len() as u64)).unwrap();
+	file.write_all(interrupted[new_blocks.len()..].as_bytes()).unwrap();
+	let trailing = fs::read(&path).unwrap();
+	assert!(trailing.len() > bytes.len());
+	assert_eq!(read(&trailing[..], key).unwrap(), new_blocks);
+
+	let mut edit = MemoryEditor::open_append(&mut file, key).unwrap();
+	edit.create_file(b"e", b"new", key);
+	edit.finish_append(&mut file, key).unwrap();
+	drop(file);
+	let bytes = fs::read(&path).unwrap();
+	let blocks = read(&bytes[..], key).unwrap();
+	assert_eq!(blocks.len() * BLOCK_SIZE, bytes.len());
+	check(&blocks, &[(b"a", Some(b"new")), (b"d", None), (b"e", Some(b"new"))]);
+
+	// Overwriting an existing file in place copies its section to the appended blocks
+	let mut file = fs::OpenOptions::new().read(true).write(true).open(&path).unwrap();
+	let mut edit = MemoryEditor::open_append(&mut file, key).unwrap();
+	let mut writer = edit.edit_file(b"a").writer(key);
+	writer.write_all(b"NE").unwrap();
+	writer.finish();
+	edit.finish_append(&mut file, key).unwrap();
+	let appended = fs::read(&path).unwrap();
+	assert_eq!(&appended[Header::BLOCKS_LEN * BLOCK_SIZE..bytes.len()], &bytes[Header::BLOCKS_LEN * BLOCK_SIZE..]);
+	let blocks = read(&appended[..], key).unwrap();
+	check(&blocks, &[(b"a", Some(b"NEw")), (b"e", Some(b"new"))]);
+
+	// Compacted blocks cannot be appended without overwriting the old blocks
+	let mut edit = MemoryEditor::open_append(&mut file, key).unwrap();
+	edit.gc();
+	assert_eq!(edit.finish_append(&mut file, key).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+	assert_eq!(MemoryEditor::new().finish_append(&mut file, key).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+	assert_eq!(fs::read(&path).unwrap(), appended);
+	drop(file);
+	let _ = fs::remove_file(&path);
 }
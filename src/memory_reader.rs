@@ -17,6 +17,7 @@ fn read_directory<'a>(blocks: &'a [Block], info: &InfoHeader) -> &'a [Descriptor
 #[derive(Clone)]
 pub struct MemoryReadIter<'a> {
 	memory_reader: &'a MemoryReader<'a>,
+	plain: Option<&'a [Descriptor]>,
 	start: u32,
 	end: u32,
 }
@@ -26,19 +27,28 @@ impl<'a> Iterator for MemoryReadIter<'a> {
 		if self.start >= self.end {
 			return None;
 		}
-		let desc = crypt::decrypt_desc(&self.memory_reader.directory[self.start as usize], &crypt::counter(&self.memory_reader.dirnonce, self.start as usize), &self.memory_reader.key);
+		let desc = match self.plain {
+			Some(plain) => plain[self.start as usize],
+			None => self.memory_reader.decrypt_desc(self.start as usize),
+		};
 		self.start = directory::next_sibling(&desc, self.start as usize, self.end as usize) as u32;
 		Some(desc)
 	}
 }
 
 /// Reads a PAK file from memory with on-the-fly decryption.
+///
+/// The directory can optionally be decrypted once and cached with [`cache_directory`](#method.cache_directory).
 #[derive(Copy, Clone, Default)]
 pub struct MemoryReader<'a> {
 	blocks: &'a [Block],
 	key: Key,
 	directory: &'a [Descriptor],
 	dirnonce: Block,
+	sorted: bool,
+	path_index: &'a [Block],
+	index_nonce: Block,
+	case: directory::Case,
 }
 impl<'a> MemoryReader<'a> {
 	/// Constructs a new `MemoryReader` from the blocks and key.
@@ -54,23 +64,124 @@ impl<'a> MemoryReader<'a> {
 		let header1 = unsafe { &*(blocks.as_ptr() as *const Header) };
 		// Decrypt the header and extract the root section
 		let header = crypt::decrypt_header(header1, key);
+		MemoryReader::from_info(blocks, key, &header)
+	}
+	fn from_info(blocks: &'a [Block], key: &Key, info: &InfoHeader) -> MemoryReader<'a> {
 		// Figure out the directory and if it's invalid just return an empty one
-		let directory = read_directory(blocks, &header);
-		MemoryReader { blocks, key: *key, directory, dirnonce: header.directory.nonce }
+		let directory = read_directory(blocks, info);
+		let path_index = path_index::section(blocks, info, key).unwrap_or(&[]);
+		MemoryReader {
+			blocks,
+			key: *key,
+			directory,
+			dirnonce: info.directory.nonce,
+			sorted: info.is_sorted(),
+			path_index,
+			index_nonce: path_index::nonce(&info.directory),
+			case: directory::Case::Sensitive,
+		}
+	}
+	/// Constructs a new `MemoryReader` from the blocks and key.
+	///
+	/// Unlike `from_blocks` the header is validated and any problems are reported.
+	pub fn try_from_blocks(blocks: &'a [Block], key: &Key) -> Result<MemoryReader<'a>> {
+		if blocks.len() < Header::BLOCKS_LEN {
+			return Err(Error::Truncated);
+		}
+		let header1 = unsafe { &*(blocks.as_ptr() as *const Header) };
+		let header = crypt::decrypt_header(header1, key);
+		error::check_info(&header, blocks.len())?;
+		if header.has_path_index() && path_index::section(blocks, &header, key).is_none() {
+			return Err(Error::Truncated);
+		}
+		Ok(MemoryReader::from_info(blocks, key, &header))
 	}
 	/// Returns if this MemoryReader contains no files or directories.
 	pub fn is_empty(&self) -> bool {
 		self.directory.is_empty()
 	}
+	/// Decrypts the directory once and keeps the plaintext.
+	///
+	/// Returns a reader serving lookups and iteration from the plaintext, see [`CachedMemoryReader`](struct.CachedMemoryReader.html).
+	pub fn cache_directory(self) -> CachedMemoryReader<'a> {
+		let blocks: &[Block] = self.directory.as_data_view().slice_tail(0);
+		let plain: Vec<Descriptor> = (0..self.directory.len()).map(|i| crypt::decrypt_desc_at(blocks, i, &self.dirnonce, &self.key)).collect();
+		let index = if self.sorted { Some(directory::SiblingIndex::new(&plain)) } else { None };
+		CachedMemoryReader { reader: self, plain, index }
+	}
+	/// Returns if the siblings of every directory are sorted by name.
+	pub fn is_sorted(&self) -> bool {
+		self.sorted
+	}
+	pub(crate) fn blocks(&self) -> &'a [Block] {
+		self.blocks
+	}
+	pub(crate) fn dir_len(&self) -> usize {
+		self.directory.len()
+	}
+	pub(crate) fn key(&self) -> &Key {
+		&self.key
+	}
+	pub(crate) fn decrypt_desc(&self, index: usize) -> Descriptor {
+		crypt::decrypt_desc(&self.directory[index], &crypt::counter(&self.dirnonce, index * Descriptor::BLOCKS_LEN), &self.key)
+	}
+	/// Sets the case sensitivity of path lookups.
+	///
+	/// Case insensitive lookups cannot use the path index nor binary search and walk the directory instead.
+	pub fn set_case(&mut self, case: directory::Case) {
+		self.case = case;
+	}
+	/// Returns the case sensitivity of path lookups.
+	pub fn case(&self) -> directory::Case {
+		self.case
+	}
+	/// Returns if the PAK file has a hashed path index.
+	pub fn has_path_index(&self) -> bool {
+		!self.path_index.is_empty()
+	}
 	/// Finds a descriptor by its path.
+	///
+	/// Uses the hashed path index if available, falling back to walking the directory on hash collisions.
 	pub fn find(&self, path: &[u8]) -> Option<Descriptor> {
-		directory::find_encrypted(self.directory, path, &self.dirnonce, &self.key)
+		self.find_normalized(&path::normalize(path).ok()?)
+	}
+	fn find_normalized(&self, path: &[u8]) -> Option<Descriptor> {
+		match self.find_indexed(path, |i| self.decrypt_desc(i)) {
+			Some(found) => found,
+			None => self.find_range(0, self.directory.len(), path),
+		}
+	}
+	// Looks up the normalized path in the path index, returns None if the directory must be walked instead
+	fn find_indexed<G: Fn(usize) -> Descriptor>(&self, path: &[u8], get: G) -> Option<Option<Descriptor>> {
+		if !self.has_path_index() || self.case != directory::Case::Sensitive {
+			return None;
+		}
+		let get_block = |j| crypt::decrypt_block_at(self.path_index, j, &self.index_nonce, &self.key);
+		match path_index::lookup(self.path_index.len(), path, get_block) {
+			path_index::Lookup::Missing => Some(None),
+			path_index::Lookup::Found(i, parent) if i < self.directory.len() && parent <= i => {
+				// Guard against a colliding hash for a path which does not exist
+				path_index::verify(self.path_index.len(), path, i, parent, get, get_block).map(Some)
+			},
+			_ => None,
+		}
+	}
+	fn find_range(&self, start: usize, end: usize, path: &[u8]) -> Option<Descriptor> {
+		directory::find_by(end - start, path, self.sorted, self.case, |i| self.decrypt_desc(start + i)).map(|(_, desc)| desc)
+	}
+	/// Finds a descriptor by its path.
+	///
+	/// Returns `InvalidPath` if the path is invalid or `NotFound` if no descriptor exists at the given path.
+	pub fn try_find(&self, path: &[u8]) -> Result<Descriptor> {
+		self.find_normalized(&path::normalize(path)?).ok_or(Error::NotFound)
 	}
 	/// Finds a descriptor by its path starting from the given root directory.
 	pub fn find_sub(&self, root: &Descriptor, path: &[u8]) -> Option<Descriptor> {
-		let subdir = &self.directory[root.section.range_usize()];
-		let nonce = crypt::counter(&self.dirnonce, root.section.offset as usize * Descriptor::BLOCKS_LEN);
-		directory::find_encrypted(subdir, path, &nonce, &self.key)
+		let range = root.section.range_usize();
+		if range.start > range.end || range.end > self.directory.len() {
+			return None;
+		}
+		self.find_range(range.start, range.end, &path::normalize(path).ok()?)
 	}
 	/// Returns if the descriptor is a valid file.
 	///
@@ -86,6 +197,15 @@ impl<'a> MemoryReader<'a> {
 			self.blocks.get(desc.section.range_usize()).is_some() &&
 			bytes2blocks(desc.content_size) <= desc.section.size;
 	}
+	fn check_file(&self, desc: &Descriptor) -> Result<()> {
+		if !desc.is_file() {
+			return Err(Error::NotAFile);
+		}
+		if !self.is_valid_file(desc) {
+			return Err(Error::OutOfRange);
+		}
+		Ok(())
+	}
 	/// Returns if the descriptor is a valid directory.
 	///
 	/// A valid directory descriptor is defined by:
@@ -113,6 +233,15 @@ impl<'a> MemoryReader<'a> {
 		}
 		bytes
 	}
+	/// Decrypts the contents of the given file descriptor.
+	///
+	/// Returns `NotAFile` if given a directory descriptor and `OutOfRange` if the descriptor is corrupt.
+	pub fn try_read_data(&self, desc: &Descriptor) -> Result<Vec<u8>> {
+		self.check_file(desc)?;
+		let mut bytes = vec![0; desc.content_size as usize];
+		crypt::decrypt_data(&self.blocks[desc.section.range_usize()], &desc.section.nonce, &self.key, 0, &mut bytes);
+		Ok(bytes)
+	}
 	/// Decrypts the contents of the given file descriptor into the dest buffer.
 	/// Given a byte offset into the file where to start decrypting.
 	///
@@ -126,11 +255,327 @@ impl<'a> MemoryReader<'a> {
 			crypt::decrypt_data(blocks, &desc.section.nonce, &self.key, byte_offset, dest);
 		}
 	}
+	/// Decrypts the contents of the given file descriptor into the dest buffer.
+	/// Given a byte offset into the file where to start decrypting.
+	///
+	/// Returns `NotAFile` if given a directory descriptor and `OutOfRange` if the descriptor is corrupt.
+	/// Returns `OutOfRange` if the requested range is not within the file's contents.
+	pub fn try_read_into(&self, desc: &Descriptor, byte_offset: usize, dest: &mut [u8]) -> Result<()> {
+		self.check_file(desc)?;
+		if byte_offset.checked_add(dest.len()).is_none_or(|end| end > desc.content_size as usize) {
+			return Err(Error::OutOfRange);
+		}
+		crypt::decrypt_data(&self.blocks[desc.section.range_usize()], &desc.section.nonce, &self.key, byte_offset, dest);
+		Ok(())
+	}
+	/// Opens the given file descriptor for streaming its decrypted contents.
+	///
+	/// Returns `None` if the descriptor is not a valid file.
+	pub fn open(&self, desc: &Descriptor) -> Option<FileReader<'a>> {
+		if !self.is_valid_file(desc) {
+			return None;
+		}
+		let blocks = self.blocks.get(desc.section.range_usize())?;
+		Some(FileReader::new(blocks.as_bytes(), &self.key, desc))
+	}
+	/// Opens the given file descriptor for streaming its decrypted contents.
+	///
+	/// Returns `NotAFile` if given a directory descriptor and `OutOfRange` if the descriptor is corrupt.
+	pub fn try_open(&self, desc: &Descriptor) -> Result<FileReader<'a>> {
+		self.check_file(desc)?;
+		Ok(FileReader::new(self.blocks[desc.section.range_usize()].as_bytes(), &self.key, desc))
+	}
 	pub fn iter(&self, desc: &Descriptor) -> MemoryReadIter<'_> {
 		MemoryReadIter {
 			memory_reader: self,
+			plain: None,
 			start: desc.section.offset,
-			end: desc.section.offset + desc.section.size,
+			end: u32::min(desc.section.offset.saturating_add(desc.section.size), self.directory.len() as u32),
+		}
+	}
+	/// Iterates over the direct children of the root directory.
+	pub fn iter_root(&self) -> MemoryReadIter<'_> {
+		MemoryReadIter {
+			memory_reader: self,
+			plain: None,
+			start: 0,
+			end: self.directory.len() as u32,
 		}
 	}
+	/// Finds all the descriptors matching the glob pattern.
+	///
+	/// See [`directory::glob`](directory/fn.glob.html) for more information.
+	pub fn glob(&self, pattern: &[u8]) -> Vec<(Vec<u8>, Descriptor)> {
+		directory::glob_encrypted(self.directory, pattern, &self.dirnonce, &self.key)
+	}
+	/// Walks the entire directory depth-first.
+	///
+	/// Yields every descriptor together with its full path, see [`directory::walk`](directory/fn.walk.html) for more information.
+	pub fn walk(&self) -> directory::Walk<'_> {
+		directory::walk_encrypted(self.directory, &self.dirnonce, &self.key)
+	}
+}
+
+/// Reads a PAK file from memory with the directory decrypted once and cached as plaintext.
+///
+/// Created by [`MemoryReader::cache_directory`](struct.MemoryReader.html#method.cache_directory).
+/// Lookups and iteration are served from the plaintext, file contents are still decrypted on the fly.
+/// Trades memory and keeping the directory decrypted in memory for faster lookups.
+///
+/// If the PAK file is sorted a sibling index is built as well and lookups use binary search.
+#[derive(Clone)]
+pub struct CachedMemoryReader<'a> {
+	reader: MemoryReader<'a>,
+	plain: Vec<Descriptor>,
+	index: Option<directory::SiblingIndex>,
+}
+impl<'a> CachedMemoryReader<'a> {
+	/// Drops the plaintext directory and returns the underlying reader.
+	pub fn drop_directory_cache(self) -> MemoryReader<'a> {
+		self.reader
+	}
+	/// Returns the underlying reader.
+	pub fn reader(&self) -> &MemoryReader<'a> {
+		&self.reader
+	}
+	/// Returns the plaintext directory.
+	pub fn directory(&self) -> &[Descriptor] {
+		&self.plain
+	}
+	/// Returns if this CachedMemoryReader contains no files or directories.
+	pub fn is_empty(&self) -> bool {
+		self.plain.is_empty()
+	}
+	/// Sets the case sensitivity of path lookups.
+	///
+	/// See [`MemoryReader::set_case`](struct.MemoryReader.html#method.set_case) for more information.
+	pub fn set_case(&mut self, case: directory::Case) {
+		self.reader.set_case(case);
+	}
+	/// Finds a descriptor by its path.
+	pub fn find(&self, path: &[u8]) -> Option<Descriptor> {
+		self.find_normalized(&path::normalize(path).ok()?)
+	}
+	fn find_normalized(&self, path: &[u8]) -> Option<Descriptor> {
+		match self.reader.find_indexed(path, |i| self.plain[i]) {
+			Some(found) => found,
+			None => self.find_range(0, self.plain.len(), path),
+		}
+	}
+	fn find_range(&self, start: usize, end: usize, path: &[u8]) -> Option<Descriptor> {
+		match &self.index {
+			Some(index) if self.reader.case == directory::Case::Sensitive => index.find(&self.plain, start, path).map(|i| self.plain[i]),
+			_ => directory::find_case(&self.plain[start..end], path, self.reader.case).first().copied(),
+		}
+	}
+	/// Finds a descriptor by its path.
+	///
+	/// Returns `InvalidPath` if the path is invalid or `NotFound` if no descriptor exists at the given path.
+	pub fn try_find(&self, path: &[u8]) -> Result<Descriptor> {
+		self.find_normalized(&path::normalize(path)?).ok_or(Error::NotFound)
+	}
+	/// Finds a descriptor by its path starting from the given root directory.
+	pub fn find_sub(&self, root: &Descriptor, path: &[u8]) -> Option<Descriptor> {
+		let range = root.section.range_usize();
+		if range.start > range.end || range.end > self.plain.len() {
+			return None;
+		}
+		self.find_range(range.start, range.end, &path::normalize(path).ok()?)
+	}
+	/// Decrypts the contents of the given file descriptor.
+	///
+	/// See [`MemoryReader::read_data`](struct.MemoryReader.html#method.read_data) for more information.
+	pub fn read_data(&self, desc: &Descriptor) -> Vec<u8> {
+		self.reader.read_data(desc)
+	}
+	/// Decrypts the contents of the given file descriptor into the dest buffer.
+	///
+	/// See [`MemoryReader::read_into`](struct.MemoryReader.html#method.read_into) for more information.
+	pub fn read_into(&self, desc: &Descriptor, byte_offset: usize, dest: &mut [u8]) {
+		self.reader.read_into(desc, byte_offset, dest)
+	}
+	/// Opens the given file descriptor for streaming its decrypted contents.
+	///
+	/// Returns `None` if the descriptor is not a valid file.
+	pub fn open(&self, desc: &Descriptor) -> Option<FileReader<'a>> {
+		self.reader.open(desc)
+	}
+	pub fn iter(&self, desc: &Descriptor) -> MemoryReadIter<'_> {
+		MemoryReadIter {
+			memory_reader: &self.reader,
+			plain: Some(&self.plain),
+			start: desc.section.offset,
+			end: u32::min(desc.section.offset.saturating_add(desc.section.size), self.plain.len() as u32),
+		}
+	}
+	/// Iterates over the direct children of the root directory.
+	pub fn iter_root(&self) -> MemoryReadIter<'_> {
+		MemoryReadIter {
+			memory_reader: &self.reader,
+			plain: Some(&self.plain),
+			start: 0,
+			end: self.plain.len() as u32,
+		}
+	}
+	/// Finds all the descriptors matching the glob pattern.
+	///
+	/// See [`directory::glob`](directory/fn.glob.html) for more information.
+	pub fn glob(&self, pattern: &[u8]) -> Vec<(Vec<u8>, Descriptor)> {
+		directory::glob(&self.plain, pattern)
+	}
+	/// Walks the entire directory depth-first.
+	///
+	/// Yields every descriptor together with its full path, see [`directory::walk`](directory/fn.walk.html) for more information.
+	pub fn walk(&self) -> directory::Walk<'_> {
+		directory::walk(&self.plain)
+	}
+}
+
+#[test]
+fn test_iter() {
+	let key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	edit.create_file(b"sub/a", b"a", key);
+	edit.create_file(b"sub/b", b"b", key);
+	edit.create_file(b"c", b"c", key);
+	let (blocks, _) = edit.finish(key);
+	let reader = MemoryReader::from_blocks(&blocks, key);
+
+	let root: Vec<_> = reader.iter_root().map(|desc| desc.name().to_vec()).collect();
+	assert_eq!(root, [b"sub".to_vec(), b"c".to_vec()]);
+	let sub = reader.find(b"sub").unwrap();
+	let children: Vec<_> = reader.iter(&sub).map(|desc| desc.name().to_vec()).collect();
+	assert_eq!(children, [b"a".to_vec(), b"b".to_vec()]);
+	let paths: Vec<_> = reader.walk().map(|(path, _)| path).collect();
+	assert_eq!(paths, [&b"sub"[..], b"sub/a", b"sub/b", b"c"]);
+	let found: Vec<_> = reader.glob(b"*/?").into_iter().map(|(path, _)| path).collect();
+	assert_eq!(found, [b"sub/a", b"sub/b"]);
+}
+
+#[test]
+fn test_cache_directory() {
+	let key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	edit.create_file(b"sub/a", b"a", key);
+	edit.create_file(b"sub/b", b"b", key);
+	edit.create_file(b"c", b"c", key);
+	let (blocks, _) = edit.finish(key);
+	let reader = MemoryReader::from_blocks(&blocks, key);
+
+	// The reader is still copyable, the plaintext is owned by the cached reader
+	let cached = reader.cache_directory();
+	assert_eq!(reader.find(b"sub"), cached.find(b"sub"));
+	let sub = cached.find(b"sub").unwrap();
+	assert_eq!(cached.find_sub(&sub, b"b").unwrap().name(), b"b");
+	assert_eq!(cached.read_data(&cached.find(b"sub/a").unwrap()), b"a");
+	let children: Vec<_> = cached.iter(&sub).map(|desc| desc.name().to_vec()).collect();
+	assert_eq!(children, [b"a".to_vec(), b"b".to_vec()]);
+	let plain: Vec<_> = cached.walk().collect();
+	assert_eq!(cached.glob(b"*/?").len(), 2);
+
+	let reader = cached.drop_directory_cache();
+	let encrypted: Vec<_> = reader.walk().collect();
+	assert_eq!(plain, encrypted);
+}
+
+#[test]
+fn test_sorted() {
+	let key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	edit.set_sorted(true);
+	for i in (0..50).rev() {
+		edit.create_file(format!("flat/{:02}", i).as_bytes(), &[i as u8], key);
+	}
+	edit.create_file(b"a", b"a", key);
+	let (blocks, _) = edit.finish(key);
+	let reader = MemoryReader::from_blocks(&blocks, key);
+	assert!(reader.is_sorted());
+
+	let names: Vec<_> = reader.iter_root().map(|desc| desc.name().to_vec()).collect();
+	assert_eq!(names, [b"flat".to_vec(), b"a".to_vec()]);
+	let cached = reader.cache_directory();
+	let find = |path: &[u8]| reader.find(path);
+	let find_sub = |root: &Descriptor, path: &[u8]| reader.find_sub(root, path);
+	let find_cached = |path: &[u8]| cached.find(path);
+	let find_sub_cached = |root: &Descriptor, path: &[u8]| cached.find_sub(root, path);
+	let finders: [(&dyn Fn(&[u8]) -> Option<Descriptor>, &dyn Fn(&Descriptor, &[u8]) -> Option<Descriptor>); 2] = [(&find, &find_sub), (&find_cached, &find_sub_cached)];
+	for (find, find_sub) in finders {
+		for i in 0..50 {
+			let desc = find(format!("flat/{:02}", i).as_bytes()).unwrap();
+			assert_eq!(reader.read_data(&desc), [i as u8]);
+		}
+		assert!(find(b"flat/50").is_none());
+		let flat = find(b"flat").unwrap();
+		assert_eq!(find_sub(&flat, b"07").unwrap().name(), b"07");
+	}
+	assert!(BytesReader::from_bytes(blocks.as_bytes(), key).find(b"flat/42").is_some());
+	let mut io_reader = IoReader::new(std::io::Cursor::new(blocks.as_bytes()), key).unwrap();
+	assert_eq!(io_reader.find(b"flat/42").unwrap().unwrap().name(), b"42");
+	assert!(io_reader.find(b"flat/50").unwrap().is_none());
+	assert!(io_reader.find(b"a").unwrap().is_some());
+
+	// Editing keeps the PAK file sorted
+	let mut edit = MemoryEditor::from_blocks(blocks, key);
+	edit.create_file(b"flat/000", b"", key);
+	let (blocks, dir) = edit.finish(key);
+	assert_eq!(dir[1].name(), b"00");
+	assert_eq!(dir[2].name(), b"000");
+	assert!(MemoryReader::from_blocks(&blocks, key).is_sorted());
+}
+
+#[test]
+fn test_case() {
+	let key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	edit.set_sorted(true);
+	edit.set_path_index(true);
+	edit.create_file("Textures/Ärger.PNG".as_bytes(), b"png", key);
+	let (blocks, _) = edit.finish(key);
+	let mut reader = MemoryReader::from_blocks(&blocks, key);
+	let mut cached = reader.cache_directory();
+	let path = "textures/ärger.png".as_bytes();
+
+	assert!(reader.find(path).is_none());
+	assert!(cached.find(path).is_none());
+	reader.set_case(directory::Case::Ascii);
+	cached.set_case(directory::Case::Ascii);
+	assert!(reader.find(path).is_none());
+	assert!(cached.find(path).is_none());
+	assert!(reader.find("textures/Ärger.png".as_bytes()).is_some());
+	assert!(cached.find("textures/Ärger.png".as_bytes()).is_some());
+	reader.set_case(directory::Case::Unicode);
+	cached.set_case(directory::Case::Unicode);
+	assert_eq!(reader.read_data(&reader.find(path).unwrap()), b"png");
+	assert_eq!(cached.read_data(&cached.find(path).unwrap()), b"png");
+}
+
+#[test]
+fn test_errors() {
+	let key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	edit.create_file(b"sub/file", b"content", key);
+	let (mut blocks, _) = edit.finish(key);
+
+	assert_eq!(MemoryReader::try_from_blocks(&blocks[..2], key).err(), Some(Error::Truncated));
+	assert_eq!(MemoryReader::try_from_blocks(&blocks, &[42, 13]).err(), Some(Error::BadKey));
+	assert_eq!(MemoryReader::try_from_blocks(&blocks[..blocks.len() - 1], key).err(), Some(Error::Truncated));
+
+	let reader = MemoryReader::try_from_blocks(&blocks, key).unwrap();
+	assert_eq!(reader.try_find(b"sub/missing").err(), Some(Error::NotFound));
+	let sub = reader.try_find(b"sub").unwrap();
+	assert_eq!(reader.try_read_data(&sub).err(), Some(Error::NotAFile));
+	let mut file = reader.try_find(b"sub/file").unwrap();
+	assert_eq!(reader.try_read_data(&file).unwrap(), b"content");
+	let mut dest = [0; 4];
+	assert_eq!(reader.try_read_into(&file, 4, &mut dest).err(), Some(Error::OutOfRange));
+	file.section.offset = 1;
+	assert_eq!(reader.try_read_data(&file).err(), Some(Error::OutOfRange));
+
+	// Bump the version of the header
+	let mut header = *blocks[..Header::BLOCKS_LEN].as_data_view().read::<Header>(0);
+	crypt::decrypt_header_inplace(&mut header, key);
+	header.info.version += 1;
+	crypt::encrypt_header_inplace(&mut header, key);
+	blocks[..Header::BLOCKS_LEN].as_data_view_mut().write(0, &header);
+	assert_eq!(MemoryReader::try_from_blocks(&blocks, key).err(), Some(Error::BadVersion));
 }
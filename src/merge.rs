@@ -0,0 +1,105 @@
+/*!
+Merging PAK files.
+
+Combines the files of several PAK files, each with its own key, into a new PAK file, see [`merge`](fn.merge.html).
+*/
+
+use crate::*;
+
+/// Policy to resolve paths existing in more than one PAK file when merging.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Conflict {
+	/// The file from the PAK file merged last replaces the existing one.
+	#[default]
+	LastWins,
+	/// The existing file is kept.
+	FirstWins,
+	/// Merging fails with `AlreadyExists`.
+	Error,
+}
+
+/// Merges several PAK files, each with its own key, into a new PAK file encrypted with the given key.
+///
+/// See [`MemoryEditor::merge`](../struct.MemoryEditor.html#method.merge) for how paths existing in more than one PAK file are resolved.
+/// The merged PAK file is compacted, see [`MemoryEditor::gc`](../struct.MemoryEditor.html#method.gc).
+/// Returns the new PAK file and its unencrypted directory like [`MemoryEditor::finish`](../struct.MemoryEditor.html#method.finish).
+///
+/// # Examples
+///
+/// ```
+/// let key = &[13, 42];
+/// let mut base = pak::MemoryEditor::new();
+/// base.create_file(b"data/a", b"base", key);
+/// let (base, _) = base.finish(key);
+///
+/// let dlc_key = &[1, 2];
+/// let mut dlc = pak::MemoryEditor::new();
+/// dlc.create_file(b"data/a", b"dlc", dlc_key);
+/// dlc.create_file(b"data/b", b"dlc", dlc_key);
+/// let (dlc, _) = dlc.finish(dlc_key);
+///
+/// let (merged, _) = pak::merge::merge(&[(&base, key), (&dlc, dlc_key)], key, pak::merge::Conflict::FirstWins).unwrap();
+/// let reader = pak::MemoryReader::from_blocks(&merged, key);
+/// assert_eq!(reader.read_data(&reader.find(b"data/a").unwrap()), b"base");
+/// assert_eq!(reader.read_data(&reader.find(b"data/b").unwrap()), b"dlc");
+/// ```
+pub fn merge(inputs: &[(&[Block], &Key)], key: &Key, conflict: Conflict) -> Result<(Vec<Block>, Vec<Descriptor>)> {
+	let mut edit = MemoryEditor::new();
+	for &(blocks, input_key) in inputs {
+		let reader = MemoryReader::try_from_blocks(blocks, input_key)?;
+		edit.merge(&reader, key, conflict)?;
+	}
+	edit.gc();
+	Ok(edit.finish(key))
+}
+
+#[test]
+fn test_merge() {
+	let key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	edit.create_file(b"data/a", b"first", key);
+	edit.create_file(b"data/b/c", b"first", key);
+	edit.create_file(b"other", b"first", key);
+	let (first, _) = edit.finish(key);
+
+	let second_key = &[1, 2];
+	let mut edit = MemoryEditor::new();
+	edit.create_file(b"data/a", b"second", second_key);
+	edit.create_file(b"data/b", b"second", second_key);
+	edit.create_file(b"data/new", b"second", second_key);
+	edit.create_file(b"other/d", b"second", second_key);
+	let (second, _) = edit.finish(second_key);
+
+	let read = |blocks: &[Block], path: &[u8]| {
+		let reader = MemoryReader::from_blocks(blocks, key);
+		reader.find(path).map(|desc| reader.read_data(&desc))
+	};
+	let inputs = [(&first[..], key), (&second[..], second_key)];
+
+	let (merged, dir) = merge(&inputs, key, Conflict::LastWins).unwrap();
+	// The replaced files are compacted away, only the 4 files of the second PAK file remain
+	assert_eq!(merged.len() - Header::BLOCKS_LEN - dir.len() * Descriptor::BLOCKS_LEN, 4);
+	assert_eq!(read(&merged, b"data/a").unwrap(), b"second");
+	assert_eq!(read(&merged, b"data/b").unwrap(), b"second");
+	assert_eq!(read(&merged, b"data/b/c"), None);
+	assert_eq!(read(&merged, b"other/d").unwrap(), b"second");
+	assert_eq!(read(&merged, b"data/new").unwrap(), b"second");
+
+	let (merged, dir) = merge(&inputs, key, Conflict::FirstWins).unwrap();
+	assert_eq!(read(&merged, b"data/a").unwrap(), b"first");
+	assert_eq!(read(&merged, b"data/b/c").unwrap(), b"first");
+	assert_eq!(read(&merged, b"other").unwrap(), b"first");
+	assert_eq!(read(&merged, b"data/new").unwrap(), b"second");
+	assert_eq!(dir.len(), 6);
+
+	assert_eq!(merge(&inputs, key, Conflict::Error).err(), Some(Error::AlreadyExists));
+	assert_eq!(merge(&inputs[..1], second_key, Conflict::Error).unwrap().1.len(), 5);
+	assert!(merge(&[(&first, second_key)], key, Conflict::Error).is_err());
+
+	// Merging checks the paths against the case sensitivity of the editor
+	let mut edit = MemoryEditor::new();
+	edit.set_case(directory::Case::Ascii);
+	edit.create_file(b"DATA/x", b"x", key);
+	let reader = MemoryReader::from_blocks(&first, key);
+	assert_eq!(edit.merge(&reader, key, Conflict::LastWins).err(), Some(Error::CaseConflict));
+}
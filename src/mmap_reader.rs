@@ -0,0 +1,145 @@
+use std::{fs, io, path::Path};
+use crate::*;
+
+/// Reads a PAK file from a read-only memory mapped file with on-the-fly decryption.
+///
+/// The contents are paged in by the OS on demand instead of being read up front.
+///
+/// Modifying or truncating the file on disk while it is mapped results in undefined behavior.
+pub struct MmapReader {
+	mmap: memmap2::Mmap,
+	key: Key,
+	info: InfoHeader,
+}
+impl MmapReader {
+	/// Maps the PAK file at the given path.
+	///
+	/// Returns an error converted from [`Error`](enum.Error.html) if the file does not encode a PAK file.
+	pub fn from_path<P: AsRef<Path>>(path: P, key: &Key) -> io::Result<MmapReader> {
+		let file = fs::File::open(path)?;
+		MmapReader::from_file(&file, key)
+	}
+	/// Maps the PAK file from an opened file.
+	///
+	/// Returns an error converted from [`Error`](enum.Error.html) if the file does not encode a PAK file.
+	pub fn from_file(file: &fs::File, key: &Key) -> io::Result<MmapReader> {
+		let mmap = unsafe { memmap2::Mmap::map(file)? };
+		// Read and decrypt the header
+		let header = mmap.as_data_view().try_copy::<Header>(0).ok_or(Error::Truncated)?;
+		let info = crypt::decrypt_header(&header, key);
+		// Validate the directory against the mapped length
+		error::check_info(&info, mmap.len() / BLOCK_SIZE)?;
+		Ok(MmapReader { mmap, key: *key, info })
+	}
+	/// Returns a `BytesReader` over the mapped bytes.
+	#[inline]
+	pub fn as_bytes_reader(&self) -> BytesReader<'_> {
+		BytesReader::from_info(&self.mmap, &self.key, &self.info)
+	}
+	/// Returns if this MmapReader contains no files or directories.
+	pub fn is_empty(&self) -> bool {
+		self.as_bytes_reader().is_empty()
+	}
+	/// Finds a descriptor by its path.
+	pub fn find(&self, path: &[u8]) -> Option<Descriptor> {
+		self.as_bytes_reader().find(path)
+	}
+	/// Finds a descriptor by its path.
+	///
+	/// Returns `InvalidPath` if the path is invalid or `NotFound` if no descriptor exists at the given path.
+	pub fn try_find(&self, path: &[u8]) -> Result<Descriptor> {
+		self.as_bytes_reader().try_find(path)
+	}
+	/// Finds a descriptor by its path starting from the given root directory.
+	pub fn find_sub(&self, root: &Descriptor, path: &[u8]) -> Option<Descriptor> {
+		self.as_bytes_reader().find_sub(root, path)
+	}
+	/// Finds all the descriptors matching the glob pattern.
+	///
+	/// See [`directory::glob`](directory/fn.glob.html) for more information.
+	pub fn glob(&self, pattern: &[u8]) -> Vec<(Vec<u8>, Descriptor)> {
+		self.as_bytes_reader().glob(pattern)
+	}
+	/// Returns if the descriptor is a valid file.
+	///
+	/// See [`MemoryReader::is_valid_file`](struct.MemoryReader.html#method.is_valid_file) for more information.
+	pub fn is_valid_file(&self, desc: &Descriptor) -> bool {
+		self.as_bytes_reader().is_valid_file(desc)
+	}
+	/// Returns if the descriptor is a valid directory.
+	///
+	/// See [`MemoryReader::is_valid_dir`](struct.MemoryReader.html#method.is_valid_dir) for more information.
+	pub fn is_valid_dir(&self, desc: &Descriptor) -> bool {
+		self.as_bytes_reader().is_valid_dir(desc)
+	}
+	/// Decrypts the contents of the given file descriptor.
+	///
+	/// If given a directory descriptor an empty Vec is returned.
+	/// If the descriptor is corrupt the returned Vec may contain zeroes.
+	pub fn read_data(&self, desc: &Descriptor) -> Vec<u8> {
+		self.as_bytes_reader().read_data(desc)
+	}
+	/// Decrypts the contents of the given file descriptor.
+	///
+	/// Returns `NotAFile` if given a directory descriptor and `OutOfRange` if the descriptor is corrupt.
+	pub fn try_read_data(&self, desc: &Descriptor) -> Result<Vec<u8>> {
+		self.as_bytes_reader().try_read_data(desc)
+	}
+	/// Decrypts the contents of the given file descriptor into the dest buffer.
+	/// Given a byte offset into the file where to start decrypting.
+	///
+	/// If given a directory descriptor nothing is written to the dest buffer.
+	/// If the descriptor is corrupt nothing may be written to the dest buffer.
+	pub fn read_into(&self, desc: &Descriptor, byte_offset: usize, dest: &mut [u8]) {
+		self.as_bytes_reader().read_into(desc, byte_offset, dest)
+	}
+	/// Decrypts the contents of the given file descriptor into the dest buffer.
+	/// Given a byte offset into the file where to start decrypting.
+	///
+	/// Returns `NotAFile` if given a directory descriptor and `OutOfRange` if the descriptor is corrupt.
+	/// Returns `OutOfRange` if the requested range is not within the file's contents.
+	pub fn try_read_into(&self, desc: &Descriptor, byte_offset: usize, dest: &mut [u8]) -> Result<()> {
+		self.as_bytes_reader().try_read_into(desc, byte_offset, dest)
+	}
+	/// Opens the given file descriptor for streaming its decrypted contents.
+	///
+	/// Returns `None` if the descriptor is not a valid file.
+	pub fn open(&self, desc: &Descriptor) -> Option<FileReader<'_>> {
+		self.as_bytes_reader().open(desc)
+	}
+	/// Opens the given file descriptor for streaming its decrypted contents.
+	///
+	/// Returns `NotAFile` if given a directory descriptor and `OutOfRange` if the descriptor is corrupt.
+	pub fn try_open(&self, desc: &Descriptor) -> Result<FileReader<'_>> {
+		self.as_bytes_reader().try_open(desc)
+	}
+	pub fn iter(&self, desc: &Descriptor) -> BytesReadIter<'_> {
+		self.as_bytes_reader().iter(desc)
+	}
+	/// Iterates over the direct children of the root directory.
+	pub fn iter_root(&self) -> BytesReadIter<'_> {
+		self.as_bytes_reader().iter_root()
+	}
+}
+
+#[test]
+fn test_mmap_roundtrip() {
+	let key = &[13, 42];
+	let data = b"Hello mapped world!";
+	let mut edit = MemoryEditor::new();
+	edit.create_file(b"sub/foo", data, key);
+	let (blocks, _) = edit.finish(key);
+
+	let path = std::env::temp_dir().join(format!("pak-mmap-{}.pak", std::process::id()));
+	fs::write(&path, blocks.as_bytes()).unwrap();
+	let reader = MmapReader::from_path(&path, key).unwrap();
+	let desc = reader.find(b"sub/foo").unwrap();
+	assert!(reader.is_valid_file(&desc));
+	assert_eq!(reader.read_data(&desc), &data[..]);
+
+	// Truncated files are rejected, unmap the file before rewriting it
+	drop(reader);
+	fs::write(&path, &blocks.as_bytes()[..blocks.as_bytes().len() - 1]).unwrap();
+	assert!(MmapReader::from_path(&path, key).is_err());
+	let _ = fs::remove_file(&path);
+}
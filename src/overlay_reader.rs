@@ -0,0 +1,176 @@
+use std::collections::HashSet;
+use crate::*;
+
+/// Descriptor found in a layer of an [`OverlayReader`](struct.OverlayReader.html).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct OverlayDesc {
+	/// Index of the layer containing the descriptor.
+	pub layer: usize,
+	/// The descriptor in its layer.
+	pub desc: Descriptor,
+}
+
+enum Lookup {
+	Found(Descriptor),
+	Hidden,
+	Missing,
+}
+
+// Looks up the path in a single layer, a whiteout or a file along the path hides the path in lower layers
+fn lookup(reader: &MemoryReader, path: &[u8]) -> Lookup {
+	let mut found: Option<Descriptor> = None;
+	for name in path.split(|&chr| chr == b'/') {
+		let desc = match &found {
+			None => reader.find(name),
+			Some(parent) if parent.is_dir() => reader.find_sub(parent, name),
+			Some(_) => return Lookup::Hidden,
+		};
+		match desc {
+			Some(desc) if desc.is_whiteout() => return Lookup::Hidden,
+			Some(desc) => found = Some(desc),
+			None => return Lookup::Missing,
+		}
+	}
+	match found {
+		Some(desc) => Lookup::Found(desc),
+		None => Lookup::Missing,
+	}
+}
+
+/// Reads a stack of PAK files where later layers override earlier layers.
+///
+/// Every layer is a `MemoryReader` with its own key.
+/// A path resolves to the topmost layer containing it, unless a layer above contains a whiteout or a file along the path.
+/// Whiteouts are created with [`MemoryEditor::create_whiteout`](struct.MemoryEditor.html#method.create_whiteout).
+#[derive(Clone, Default)]
+pub struct OverlayReader<'a> {
+	layers: Vec<MemoryReader<'a>>,
+}
+impl<'a> OverlayReader<'a> {
+	/// Creates an overlay without any layers.
+	pub fn new() -> OverlayReader<'a> {
+		OverlayReader { layers: Vec::new() }
+	}
+	/// Adds a layer on top of the existing layers.
+	pub fn push(&mut self, reader: MemoryReader<'a>) {
+		self.layers.push(reader);
+	}
+	/// Returns the layers from the bottom to the top.
+	pub fn layers(&self) -> &[MemoryReader<'a>] {
+		&self.layers
+	}
+	/// Finds a descriptor by its path in the topmost layer containing it.
+	///
+	/// Returns `None` if the path is hidden by a whiteout or a file in a layer above.
+	pub fn find(&self, path: &[u8]) -> Option<OverlayDesc> {
+		self.find_normalized(&path::normalize(path).ok()?)
+	}
+	fn find_normalized(&self, path: &[u8]) -> Option<OverlayDesc> {
+		for (layer, reader) in self.layers.iter().enumerate().rev() {
+			match lookup(reader, path) {
+				Lookup::Found(desc) => return Some(OverlayDesc { layer, desc }),
+				Lookup::Hidden => return None,
+				Lookup::Missing => (),
+			}
+		}
+		None
+	}
+	/// Finds a descriptor by its path in the topmost layer containing it.
+	///
+	/// Returns `InvalidPath` if the path is invalid or `NotFound` if no descriptor exists at the given path or it is hidden.
+	pub fn try_find(&self, path: &[u8]) -> Result<OverlayDesc> {
+		self.find_normalized(&path::normalize(path)?).ok_or(Error::NotFound)
+	}
+	/// Decrypts the contents of the file from its layer.
+	///
+	/// See [`MemoryReader::read_data`](struct.MemoryReader.html#method.read_data) for more information.
+	pub fn read_data(&self, found: &OverlayDesc) -> Vec<u8> {
+		match self.layers.get(found.layer) {
+			Some(reader) => reader.read_data(&found.desc),
+			None => Vec::new(),
+		}
+	}
+	/// Decrypts the contents of the file from its layer.
+	///
+	/// Returns `OutOfRange` if the layer does not exist, see [`MemoryReader::try_read_data`](struct.MemoryReader.html#method.try_read_data) for other errors.
+	pub fn try_read_data(&self, found: &OverlayDesc) -> Result<Vec<u8>> {
+		self.layers.get(found.layer).ok_or(Error::OutOfRange)?.try_read_data(&found.desc)
+	}
+	/// Lists the direct children of the directory at the given path, the root directory if the path is empty.
+	///
+	/// The children of the directory in every layer are merged, names in upper layers override the same names in lower layers.
+	/// Whiteouts are not listed and hide the name in lower layers.
+	/// Returns the names of the children ordered from the topmost layer down.
+	pub fn read_dir(&self, path: &[u8]) -> Vec<(Vec<u8>, OverlayDesc)> {
+		let mut entries = Vec::new();
+		let path = match path {
+			b"" => None,
+			_ => match path::normalize(path) {
+				Ok(path) => Some(path),
+				Err(_) => return entries,
+			},
+		};
+		let mut names = HashSet::new();
+		for (layer, reader) in self.layers.iter().enumerate().rev() {
+			let children = match &path {
+				None => reader.iter_root(),
+				Some(path) => match lookup(reader, path) {
+					Lookup::Found(desc) if desc.is_dir() => reader.iter(&desc),
+					Lookup::Found(_) | Lookup::Hidden => break,
+					Lookup::Missing => continue,
+				},
+			};
+			for desc in children {
+				if names.insert(desc.name().to_vec()) && !desc.is_whiteout() {
+					entries.push((desc.name().to_vec(), OverlayDesc { layer, desc }));
+				}
+			}
+		}
+		entries
+	}
+}
+
+#[test]
+fn test_overlay() {
+	let key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	edit.create_file(b"data/a", b"base", key);
+	edit.create_file(b"data/b", b"base", key);
+	edit.create_file(b"data/sub/c", b"base", key);
+	edit.create_file(b"maps/e1m1", b"base", key);
+	let (base, _) = edit.finish(key);
+
+	let mod_key = &[1, 2];
+	let mut edit = MemoryEditor::new();
+	edit.create_file(b"data/a", b"mod", mod_key);
+	edit.create_file(b"data/new", b"mod", mod_key);
+	edit.create_whiteout(b"data/b");
+	edit.create_whiteout(b"data/sub");
+	edit.create_file(b"maps", b"mod", mod_key);
+	let (layer, _) = edit.finish(mod_key);
+
+	let mut overlay = OverlayReader::new();
+	overlay.push(MemoryReader::from_blocks(&base, key));
+	overlay.push(MemoryReader::from_blocks(&layer, mod_key));
+
+	let a = overlay.find(b"data/a").unwrap();
+	assert_eq!((a.layer, overlay.read_data(&a)), (1, b"mod".to_vec()));
+	assert_eq!(overlay.find(b"data/b"), None);
+	assert_eq!(overlay.find(b"data/sub/c"), None);
+	assert_eq!(overlay.find(b"maps/e1m1"), None);
+	assert_eq!(overlay.try_find(b"data/../..").err(), Some(Error::InvalidPath));
+	assert_eq!(overlay.find(b"data").unwrap().layer, 1);
+
+	fn names(overlay: &OverlayReader, path: &[u8]) -> Vec<(Vec<u8>, usize)> {
+		overlay.read_dir(path).into_iter().map(|(name, found)| (name, found.layer)).collect()
+	}
+	assert_eq!(names(&overlay, b"data"), [(b"a".to_vec(), 1), (b"new".to_vec(), 1)]);
+	assert_eq!(names(&overlay, b""), [(b"data".to_vec(), 1), (b"maps".to_vec(), 1)]);
+	assert!(names(&overlay, b"maps").is_empty());
+
+	// Layers below a hidden path are never consulted
+	overlay.push(MemoryReader::from_blocks(&base, key));
+	assert_eq!(overlay.find(b"data/b").unwrap().layer, 2);
+	assert_eq!(names(&overlay, b"data/sub"), [(b"c".to_vec(), 2)]);
+	assert_eq!(names(&overlay, b"data").len(), 4);
+}
@@ -0,0 +1,265 @@
+/*!
+Binary delta patches between PAK files.
+
+A patch turns an old PAK file into a new PAK file, see [`create`](fn.create.html) and [`apply`](fn.apply.html).
+
+The patch contains the new directory and only the file contents not found in the old PAK file.
+The first block holds a magic number and version, followed by a random nonce in plaintext.
+The table follows encrypted with the new key, starting with the number of descriptors and the header flags.
+Every descriptor is followed by the source of its contents and the hash of its decrypted contents.
+The table ends with the keyed hash of the table itself, see [`crypt::mac`](../crypt/fn.mac.html), followed by the file contents copied as is from the new PAK file.
+The keyed hash authenticates the table, only the owner of the new key can create a patch which applies.
+*/
+
+use std::slice;
+use std::collections::HashMap;
+use crate::*;
+
+const MAGIC: u64 = u64::from_le_bytes(*b"PAKPATCH");
+const VERSION: u64 = 0;
+
+// Descriptor, source and hash blocks per table entry
+const ENTRY_LEN: usize = Descriptor::BLOCKS_LEN + 2;
+// Source of contents stored in the patch
+const DATA: u64 = u64::MAX;
+
+/// Creates a patch which turns the old PAK file into the new PAK file.
+///
+/// Files whose contents exist anywhere in the old PAK file are referenced, all other file contents are copied as is.
+/// The patch is encrypted with the key of the new PAK file.
+///
+/// Returns `OutOfRange` if any file in the new PAK file is not valid, invalid files in the old PAK file are ignored.
+pub fn create(old: &MemoryReader, new: &MemoryReader) -> Result<Vec<Block>> {
+	// Index the contents of the old PAK file by their hash
+	let mut old_hashes = HashMap::new();
+	for i in 0..old.dir_len() {
+		let desc = old.decrypt_desc(i);
+		if desc.is_file() && old.is_valid_file(&desc) {
+			old_hashes.entry(crypt::hash(&old.read_data(&desc))).or_insert(i);
+		}
+	}
+
+	let len = new.dir_len();
+	let mut flags = 0;
+	if new.is_sorted() {
+		flags |= InfoHeader::FLAG_SORTED;
+	}
+	if new.has_path_index() {
+		flags |= InfoHeader::FLAG_PATH_INDEX;
+	}
+	let mut table = Vec::with_capacity(1 + len * ENTRY_LEN + 1);
+	table.push([len as u64, flags as u64]);
+
+	let mut data = Vec::new();
+	let mut sections = HashMap::new();
+	for j in 0..len {
+		let mut desc = new.decrypt_desc(j);
+		let mut source = DATA;
+		let mut hash = Block::default();
+		if desc.is_file() {
+			hash = crypt::hash(&new.try_read_data(&desc)?);
+			match old_hashes.get(&hash) {
+				Some(&i) => source = i as u64,
+				None => {
+					// Sections shared in the new PAK file are copied once
+					desc.section.offset = *sections.entry(desc.section).or_insert_with(|| {
+						let offset = data.len() as u32;
+						data.extend_from_slice(&new.blocks()[desc.section.range_usize()]);
+						offset
+					});
+				},
+			}
+		}
+		table.extend_from_slice(desc.as_data_view().slice_tail(0));
+		table.push([source, 0]);
+		table.push(hash);
+	}
+	table.push(crypt::mac(table.as_bytes(), new.key()));
+
+	let mut nonce = Block::default();
+	crypt::random(slice::from_mut(&mut nonce));
+	crypt::crypt_inplace(&mut table, &nonce, new.key());
+
+	let mut patch = Vec::with_capacity(2 + table.len() + data.len());
+	patch.push([MAGIC, VERSION]);
+	patch.push(nonce);
+	patch.extend_from_slice(&table);
+	patch.extend_from_slice(&data);
+	Ok(patch)
+}
+
+/// Applies the patch to the old PAK file, rebuilding the new PAK file.
+///
+/// The key is the key of the new PAK file the patch was created from.
+/// The contents of every file are checked against the hashes in the patch.
+/// The file contents are encrypted exactly as in the new PAK file but their layout may differ.
+///
+/// Returns `BadPatch` if the patch is corrupt, the key is wrong or the patch was not created for this old PAK file.
+/// Returns the rebuilt PAK file and its unencrypted directory like [`MemoryEditor::finish`](../struct.MemoryEditor.html#method.finish).
+///
+/// # Examples
+///
+/// ```
+/// let key = &[13, 42];
+/// let mut edit = pak::MemoryEditor::new();
+/// edit.create_file(b"big", &[1; 1000], key);
+/// let (old, _) = edit.finish(key);
+///
+/// let mut edit = pak::MemoryEditor::from_blocks(old.clone(), key);
+/// edit.create_file(b"small", b"small", key);
+/// let (new, _) = edit.finish(key);
+///
+/// let old = pak::MemoryReader::from_blocks(&old, key);
+/// let patch = pak::patch::create(&old, &pak::MemoryReader::from_blocks(&new, key)).unwrap();
+/// assert!(patch.len() < new.len());
+///
+/// let (rebuilt, _) = pak::patch::apply(&old, &patch, key).unwrap();
+/// let rebuilt = pak::MemoryReader::from_blocks(&rebuilt, key);
+/// assert_eq!(rebuilt.read_data(&rebuilt.find(b"small").unwrap()), b"small");
+/// ```
+pub fn apply(old: &MemoryReader, patch: &[Block], key: &Key) -> Result<(Vec<Block>, Vec<Descriptor>)> {
+	if patch.len() < 4 || patch[0] != [MAGIC, VERSION] {
+		return Err(Error::BadPatch);
+	}
+	let nonce = patch[1];
+	let [len, flags] = crypt::decrypt_block_at(&patch[2..], 0, &nonce, key);
+	// The header, the table length, the entries and the table hash must fit
+	if len > ((patch.len() - 4) / ENTRY_LEN) as u64 {
+		return Err(Error::BadPatch);
+	}
+	let len = len as usize;
+	let table_end = 2 + 1 + len * ENTRY_LEN + 1;
+	let mut table = patch[2..table_end].to_vec();
+	crypt::crypt_inplace(&mut table, &nonce, key);
+	let (&checksum, table) = table.split_last().unwrap();
+	if crypt::mac(table.as_bytes(), key) != checksum {
+		return Err(Error::BadPatch);
+	}
+	let data = &patch[table_end..];
+
+	let mut blocks = vec![Block::default(); Header::BLOCKS_LEN];
+	let mut dir = Vec::with_capacity(len);
+	let mut sections = HashMap::new();
+	for entry in table[1..].chunks_exact(ENTRY_LEN) {
+		let mut desc: Descriptor = entry[..Descriptor::BLOCKS_LEN].as_data_view().copy(0);
+		let [source, _] = entry[Descriptor::BLOCKS_LEN];
+		let hash = entry[Descriptor::BLOCKS_LEN + 1];
+		if desc.is_file() {
+			// Files sharing a section in the new PAK file keep sharing it
+			desc.section = match sections.get(&(desc.section, source)) {
+				Some(&section) => section,
+				None => {
+					let section = copy_section(old, data, &desc.section, source, key, &mut blocks)?;
+					sections.insert((desc.section, source), section);
+					section
+				},
+			};
+			if bytes2blocks(desc.content_size) > desc.section.size {
+				return Err(Error::BadPatch);
+			}
+			let mut content = vec![0; desc.content_size as usize];
+			crypt::decrypt_data(&blocks[desc.section.range_usize()], &desc.section.nonce, key, 0, &mut content);
+			if crypt::hash(&content) != hash {
+				return Err(Error::BadPatch);
+			}
+		}
+		dir.push(desc);
+	}
+	if !directory::is_nested(&dir) {
+		return Err(Error::BadPatch);
+	}
+
+	let flags = flags as u32;
+	let edit = MemoryEditor::from_parts(blocks, dir, flags & InfoHeader::FLAG_SORTED != 0, flags & InfoHeader::FLAG_PATH_INDEX != 0);
+	Ok(edit.finish(key))
+}
+
+// Appends the contents of a file to the blocks encrypted with the section's nonce
+fn copy_section(old: &MemoryReader, data: &[Block], section: &Section, source: u64, key: &Key, blocks: &mut Vec<Block>) -> Result<Section> {
+	let offset = blocks.len();
+	if source == DATA {
+		blocks.extend_from_slice(data.get(section.range_usize()).ok_or(Error::BadPatch)?);
+	}
+	else {
+		if source >= old.dir_len() as u64 {
+			return Err(Error::BadPatch);
+		}
+		let old_desc = old.decrypt_desc(source as usize);
+		if !old_desc.is_file() || !old.is_valid_file(&old_desc) {
+			return Err(Error::BadPatch);
+		}
+		let old_blocks = &old.blocks()[old_desc.section.range_usize()];
+		let copy_len = usize::min(old_blocks.len(), section.size as usize);
+		blocks.extend_from_slice(&old_blocks[..copy_len]);
+		blocks.resize(offset + section.size as usize, Block::default());
+		if old_desc.section.nonce != section.nonce || old.key() != key {
+			crypt::reencrypt_data(&mut blocks[offset..offset + copy_len], &old_desc.section.nonce, &section.nonce, old.key(), key);
+		}
+	}
+	Ok(Section { offset: offset as u32, size: section.size, nonce: section.nonce })
+}
+
+#[test]
+fn test_patch() {
+	let old_key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	edit.create_file(b"same", &[1; 500], old_key);
+	edit.create_file(b"moved", &[2; 500], old_key);
+	edit.create_file(b"changed", &[3; 500], old_key);
+	edit.create_file(b"removed", &[4; 500], old_key);
+	let (old_blocks, _) = edit.finish(old_key);
+	let old = MemoryReader::from_blocks(&old_blocks, old_key);
+
+	let key = &[1, 2];
+	let mut edit = MemoryEditor::new();
+	edit.set_sorted(true);
+	edit.create_file(b"same", &[1; 500], key);
+	edit.create_file(b"dir/moved", &[2; 500], key);
+	edit.create_file(b"changed", &[5; 500], key);
+	edit.create_file(b"added", b"added", key);
+	edit.copy(b"added", b"link").unwrap();
+	edit.create_dir(b"empty");
+	let (new_blocks, _) = edit.finish(key);
+	let new = MemoryReader::from_blocks(&new_blocks, key);
+
+	let patch = create(&old, &new).unwrap();
+	// Only the changed and added contents are included
+	assert!(patch.len() < 2 + 1 + 8 * ENTRY_LEN + 1 + 32 + 1 + 1);
+
+	let (blocks, _) = apply(&old, &patch, key).unwrap();
+	let rebuilt = MemoryReader::from_blocks(&blocks, key);
+	assert!(rebuilt.is_sorted());
+	assert!(diff::diff(&rebuilt, &new).unwrap().is_empty());
+	assert_eq!(rebuilt.find(b"link").unwrap().section, rebuilt.find(b"added").unwrap().section);
+	assert!(rebuilt.find(b"empty").unwrap().is_dir());
+	// The contents are encrypted exactly as in the new PAK file
+	let desc = rebuilt.find(b"dir/moved").unwrap();
+	assert_eq!(&blocks[desc.section.range_usize()], &new_blocks[new.find(b"dir/moved").unwrap().section.range_usize()]);
+
+	// Wrong key, wrong old PAK file and corrupt patches are rejected
+	assert_eq!(apply(&old, &patch, old_key).err(), Some(Error::BadPatch));
+	assert_eq!(apply(&new, &patch, key).err(), Some(Error::BadPatch));
+	let mut corrupt = patch.clone();
+	corrupt[5][0] ^= 1;
+	assert_eq!(apply(&old, &corrupt, key).err(), Some(Error::BadPatch));
+	assert_eq!(apply(&old, &patch[..2], key).err(), Some(Error::BadPatch));
+
+	// Truncated patches are rejected, a patch without file contents ends with the table
+	let table_end = 4 + old.dir_len() * ENTRY_LEN;
+	let identity = create(&old, &old).unwrap();
+	assert_eq!(identity.len(), table_end);
+	assert!(apply(&old, &identity, old_key).is_ok());
+	assert_eq!(apply(&old, &identity[..table_end - 1], old_key).err(), Some(Error::BadPatch));
+	assert_eq!(apply(&old, &patch[..patch.len() - 1], key).err(), Some(Error::BadPatch));
+
+	// The table hash is keyed, a modified table cannot be fixed up without the key
+	let mut forged = identity.clone();
+	let mut table = forged[2..].to_vec();
+	crypt::crypt_inplace(&mut table, &identity[1], old_key);
+	table[1][0] ^= 1;
+	let (checksum, table_data) = table.split_last_mut().unwrap();
+	*checksum = crypt::hash(table_data.as_bytes());
+	crypt::crypt_inplace(&mut table, &identity[1], old_key);
+	forged[2..].copy_from_slice(&table);
+	assert_eq!(apply(&old, &forged, old_key).err(), Some(Error::BadPatch));
+}
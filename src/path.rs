@@ -0,0 +1,102 @@
+/*!
+Path normalization and validation.
+
+Paths are sequences of names separated by `/` or `\`.
+The readers and the editor normalize every path before using it, see [`normalize`](fn.normalize.html).
+*/
+
+use std::borrow::Cow;
+use crate::*;
+
+fn is_separator(chr: u8) -> bool {
+	chr == b'/' || chr == b'\\'
+}
+
+/// Checks if the name is valid for a descriptor.
+///
+/// A valid name is not empty, is not `.` or `..`, fits in the descriptor's name buffer and does not contain separators or NUL bytes.
+///
+/// # Examples
+///
+/// ```
+/// use pak::path::check_name;
+///
+/// assert!(check_name(b"file.txt").is_ok());
+/// assert!(check_name(b"..").is_err());
+/// assert!(check_name(b"a\0b").is_err());
+/// assert!(check_name(b"a/b").is_err());
+/// ```
+pub fn check_name(name: &[u8]) -> Result<()> {
+	if name.is_empty() || name == b"." || name == b".." || name.len() > 31 {
+		return Err(Error::InvalidPath);
+	}
+	if name.iter().any(|&chr| chr == 0 || is_separator(chr)) {
+		return Err(Error::InvalidPath);
+	}
+	Ok(())
+}
+
+/// Normalizes the path.
+///
+/// * Repeated, leading and trailing separators are collapsed and backslashes are replaced by forward slashes.
+/// * The `.` components are removed and `..` components remove the previous component.
+///
+/// Returns `InvalidPath` if the path is empty after normalization, `..` escapes the root or a name is not valid, see [`check_name`](fn.check_name.html).
+/// The path is borrowed if it is already normalized.
+///
+/// # Examples
+///
+/// ```
+/// use pak::path::normalize;
+///
+/// assert_eq!(&*normalize(b"a/b").unwrap(), b"a/b");
+/// assert_eq!(&*normalize(b"/a//./b\\c/../d/").unwrap(), b"a/b/d");
+/// assert!(normalize(b"a/../..").is_err());
+/// assert!(normalize(b"./").is_err());
+/// ```
+pub fn normalize(path: &[u8]) -> Result<Cow<'_, [u8]>> {
+	// Fast path if the path is already normalized
+	if !path.is_empty() && path.split(|&chr| chr == b'/').all(|name| check_name(name).is_ok()) {
+		return Ok(Cow::Borrowed(path));
+	}
+	let mut normalized: Vec<u8> = Vec::with_capacity(path.len());
+	for name in path.split(|&chr| is_separator(chr)) {
+		match name {
+			b"" | b"." => (),
+			b".." => {
+				if normalized.is_empty() {
+					return Err(Error::InvalidPath);
+				}
+				let parent_len = normalized.iter().rposition(|&chr| chr == b'/').unwrap_or(0);
+				normalized.truncate(parent_len);
+			},
+			_ => {
+				check_name(name)?;
+				if !normalized.is_empty() {
+					normalized.push(b'/');
+				}
+				normalized.extend_from_slice(name);
+			},
+		}
+	}
+	if normalized.is_empty() {
+		return Err(Error::InvalidPath);
+	}
+	Ok(Cow::Owned(normalized))
+}
+
+#[test]
+fn test_normalize() {
+	let ok = |path: &[u8]| normalize(path).unwrap().into_owned();
+	assert_eq!(ok(b"file"), b"file");
+	assert_eq!(ok(b"a\\b"), b"a/b");
+	assert_eq!(ok(b"//a///b//"), b"a/b");
+	assert_eq!(ok(b"a/./b/."), b"a/b");
+	assert_eq!(ok(b"a/b/../c"), b"a/c");
+	assert_eq!(ok(b"a/b/c/../../d"), b"a/d");
+	assert!(matches!(normalize(b"a/b"), Ok(Cow::Borrowed(_))));
+
+	for path in [&b""[..], b"/", b".", b"a/..", b"../a", b"a/../../b", b"a\0/b", b"0123456789012345678901234567890123456789"] {
+		assert_eq!(normalize(path).err(), Some(Error::InvalidPath), "{:?}", String::from_utf8_lossy(path));
+	}
+}
@@ -0,0 +1,237 @@
+/*!
+Hashed path index.
+
+An optional section following the directory which maps the hash of a full path to the index of its descriptor.
+It is encrypted like the directory by continuing the directory's counter.
+
+The first block holds the capacity of the hash table, followed by one block per slot holding the path hash, the descriptor index in the low 32 bits and its parent in the high 32 bits.
+The parent is `0` for the root and `i + 1` for the directory descriptor at index i, readers verify a hit by following the parents up to the root.
+The slots use linear probing. Paths sharing the same hash are marked as a collision and must be looked up by walking the directory.
+*/
+
+use std::cmp;
+use crate::*;
+
+// Special descriptor indices
+const EMPTY: u64 = u64::MAX;
+const COLLISION: u64 = u64::MAX - 1;
+
+/// FNV-1a hash of the path.
+///
+/// Backslashes hash as forward slashes and a trailing separator is ignored, matching how `directory::name_eq` splits paths.
+pub(crate) fn hash(path: &[u8]) -> u64 {
+	let path = match path.last() {
+		Some(b'/') | Some(b'\\') => &path[..path.len() - 1],
+		_ => path,
+	};
+	let mut hash = 0xcbf29ce484222325u64;
+	for &chr in path {
+		let chr = if chr == b'\\' { b'/' } else { chr };
+		hash ^= chr as u64;
+		hash = hash.wrapping_mul(0x100000001b3);
+	}
+	hash
+}
+
+/// Returns the last component of the path.
+pub(crate) fn file_name(path: &[u8]) -> &[u8] {
+	let path = match path.last() {
+		Some(b'/') | Some(b'\\') => &path[..path.len() - 1],
+		_ => path,
+	};
+	match path.iter().rposition(|&chr| chr == b'/' || chr == b'\\') {
+		Some(i) => &path[i + 1..],
+		None => path,
+	}
+}
+
+/// Returns the path without its last component, `None` for a single component.
+pub(crate) fn parent_path(path: &[u8]) -> Option<&[u8]> {
+	let path = match path.last() {
+		Some(b'/') | Some(b'\\') => &path[..path.len() - 1],
+		_ => path,
+	};
+	path.iter().rposition(|&chr| chr == b'/' || chr == b'\\').map(|i| &path[..i])
+}
+
+/// Returns the nonce used to encrypt the path index.
+pub(crate) fn nonce(dir_section: &Section) -> Block {
+	crypt::counter(&dir_section.nonce, dir_section.size as usize * Descriptor::BLOCKS_LEN)
+}
+
+/// Returns the encrypted path index following the directory.
+///
+/// Returns `None` if the PAK file has no path index or if it is out of range.
+pub(crate) fn section<'a>(blocks: &'a [Block], info: &InfoHeader, key: &Key) -> Option<&'a [Block]> {
+	if !info.has_path_index() {
+		return None;
+	}
+	let start = info.directory.offset as usize + info.directory.size as usize * Descriptor::BLOCKS_LEN;
+	let first = blocks.get(start..start + 1)?;
+	let capacity = crypt::decrypt_block_at(first, 0, &nonce(&info.directory), key)[0];
+	if !capacity.is_power_of_two() || capacity > blocks.len() as u64 {
+		return None;
+	}
+	blocks.get(start..start + 1 + capacity as usize)
+}
+
+/// Builds the plaintext path index for the directory.
+pub(crate) fn build(dir: &[Descriptor]) -> Vec<Block> {
+	let capacity = cmp::max(1, dir.len() * 2).next_power_of_two();
+	let mut blocks = vec![[0, EMPTY]; 1 + capacity];
+	blocks[0] = [capacity as u64, 0];
+	let parents = directory::parents(dir);
+	for (i, (path, _)) in directory::walk(dir).enumerate() {
+		let hash = hash(&path);
+		let mut slot = hash as usize & (capacity - 1);
+		loop {
+			let entry = &mut blocks[1 + slot];
+			if entry[1] == EMPTY {
+				*entry = [hash, (parents[i] as u64) << 32 | i as u64];
+				break;
+			}
+			if entry[0] == hash {
+				entry[1] = COLLISION;
+				break;
+			}
+			slot = (slot + 1) & (capacity - 1);
+		}
+	}
+	blocks
+}
+
+/// Result of looking up a path in the path index.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Lookup {
+	/// The index and the parent of the descriptor with the path's hash.
+	///
+	/// The hash may collide with the hash of a path which does not exist, see [`verify`](fn.verify.html).
+	Found(usize, usize),
+	/// No descriptor exists with the given path.
+	Missing,
+	/// The path must be looked up by walking the directory.
+	Fallback,
+}
+
+/// Looks up the path in a path index of `len` blocks where the decrypted blocks are produced on demand.
+pub(crate) fn lookup<F: FnMut(usize) -> Block>(len: usize, path: &[u8], mut get: F) -> Lookup {
+	if len < 2 {
+		return Lookup::Fallback;
+	}
+	let capacity = get(0)[0] as usize;
+	if !capacity.is_power_of_two() || capacity + 1 != len {
+		return Lookup::Fallback;
+	}
+	let hash = hash(path);
+	let mut slot = hash as usize & (capacity - 1);
+	for _ in 0..capacity {
+		let [entry_hash, index] = get(1 + slot);
+		if index == EMPTY {
+			return Lookup::Missing;
+		}
+		if entry_hash == hash {
+			return if index == COLLISION { Lookup::Fallback } else { Lookup::Found(index as u32 as usize, (index >> 32) as usize) };
+		}
+		slot = (slot + 1) & (capacity - 1);
+	}
+	Lookup::Fallback
+}
+
+/// Verifies the full path of a descriptor found in the path index by following its parents up to the root.
+///
+/// The descriptors and the path index blocks are produced on demand.
+/// Returns `None` if the path must be looked up by walking the directory instead.
+pub(crate) fn verify<F: FnMut(usize) -> Descriptor, G: FnMut(usize) -> Block>(len: usize, mut path: &[u8], mut index: usize, mut parent: usize, mut get: F, mut get_block: G) -> Option<Descriptor> {
+	let found = get(index);
+	let mut desc = found;
+	loop {
+		if directory::name_eq(&desc, file_name(path)) != Some(&b""[..]) {
+			return None;
+		}
+		let parent_path = match parent_path(path) {
+			Some(parent_path) => parent_path,
+			None => return if parent == 0 { Some(found) } else { None },
+		};
+		// The parent must be a directory containing the descriptor and be found at the parent path
+		let (i, grandparent) = match lookup(len, parent_path, &mut get_block) {
+			Lookup::Found(i, grandparent) if i + 1 == parent => (i, grandparent),
+			_ => return None,
+		};
+		let parent_desc = get(i);
+		if !parent_desc.is_dir() || index > i + parent_desc.content_size as usize {
+			return None;
+		}
+		index = i;
+		parent = grandparent;
+		path = parent_path;
+		desc = parent_desc;
+	}
+}
+
+#[test]
+fn test_path_index() {
+	let key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	edit.set_path_index(true);
+	let paths: Vec<String> = (0..100).map(|i| format!("dir{}/file{}", i % 7, i)).collect();
+	for path in &paths {
+		edit.create_file(path.as_bytes(), path.as_bytes(), key);
+	}
+	// Both a file and a directory named `dup` share the same hash
+	edit.create_file(b"dup", b"file", key);
+	edit.create_file(b"dup/child", b"child", key);
+	let (blocks, _) = edit.finish(key);
+
+	let reader = MemoryReader::from_blocks(&blocks, key);
+	assert!(reader.has_path_index());
+	for path in &paths {
+		let desc = reader.find(path.as_bytes()).unwrap();
+		assert_eq!(reader.read_data(&desc), path.as_bytes());
+	}
+	assert!(reader.find(b"dir3\\file3").is_some());
+	assert!(reader.find(b"dir3/").unwrap().is_dir());
+	assert!(reader.find(b"dir3/file4").is_none());
+	assert!(reader.find(b"").is_none());
+	assert!(reader.find(b"dup").unwrap().is_dir());
+	assert_eq!(reader.read_data(&reader.find(b"dup/child").unwrap()), b"child");
+
+	// Reading and editing account for the path index after the directory
+	assert_eq!(io_reader::read(blocks.as_bytes(), key).unwrap(), blocks);
+	let edit = MemoryEditor::from_blocks(blocks.clone(), key);
+	let (blocks2, _) = edit.finish(key);
+	assert_eq!(blocks2.len(), blocks.len());
+	assert_eq!(MemoryReader::try_from_blocks(&blocks[..blocks.len() - 1], key).err(), Some(Error::Truncated));
+}
+
+#[test]
+fn test_verify() {
+	let mut dir = Vec::new();
+	for path in [&b"a/x"[..], b"b/x", b"b/c/x"] {
+		directory::create(&mut dir, path).content_type = 1;
+	}
+	directory::update_dir_address(&mut dir);
+	let mut index = build(&dir);
+	let find = |index: &[Block], path: &[u8]| match lookup(index.len(), path, |j| index[j]) {
+		Lookup::Found(i, parent) => verify(index.len(), path, i, parent, |i| dir[i], |j| index[j]),
+		_ => None,
+	};
+	for (path, desc) in directory::walk(&dir) {
+		assert_eq!(find(&index, &path), Some(desc));
+	}
+
+	// Simulate paths whose hash collides with the hash of an existing path ending in the same name
+	let capacity = index.len() - 1;
+	for (path, target) in [(&b"c/x"[..], &b"a/x"[..]), (b"a/x", b"b/x"), (b"x", b"b/c/x"), (b"b/b/x", b"b/c/x")] {
+		let entry = match lookup(index.len(), target, |j| index[j]) {
+			Lookup::Found(i, parent) => (parent as u64) << 32 | i as u64,
+			_ => panic!(),
+		};
+		let hash = hash(path);
+		let mut slot = hash as usize & (capacity - 1);
+		while index[1 + slot][1] != EMPTY && index[1 + slot][0] != hash {
+			slot = (slot + 1) & (capacity - 1);
+		}
+		index[1 + slot] = [hash, entry];
+		assert_eq!(find(&index, path), None);
+	}
+}
@@ -0,0 +1,284 @@
+use std::{cmp, fs, io, path::Path};
+use crate::*;
+
+#[cfg(unix)]
+fn read_exact_at(file: &fs::File, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+	use std::os::unix::fs::FileExt;
+	file.read_exact_at(buf, offset)
+}
+#[cfg(windows)]
+fn read_exact_at(file: &fs::File, mut offset: u64, mut buf: &mut [u8]) -> io::Result<()> {
+	use std::os::windows::fs::FileExt;
+	while !buf.is_empty() {
+		match file.seek_read(buf, offset) {
+			Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+			Ok(n) => {
+				buf = &mut buf[n..];
+				offset += n as u64;
+			},
+			Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+			Err(err) => return Err(err),
+		}
+	}
+	Ok(())
+}
+
+/// Reads a PAK file with positional reads on a single open file.
+///
+/// The reader is `Send + Sync` and can be shared between threads, eg. in an `Arc`.
+/// Reading does not move a shared cursor so concurrent reads do not need to be synchronized.
+///
+/// The encrypted directory is read into memory when opened and descriptors are decrypted on demand.
+pub struct SharedReader {
+	file: fs::File,
+	key: Key,
+	info: InfoHeader,
+	directory: Vec<Descriptor>,
+	blocks_len: usize,
+}
+impl SharedReader {
+	/// Opens the PAK file at the given path.
+	///
+	/// Returns an error converted from [`Error`](enum.Error.html) if the file does not encode a PAK file.
+	pub fn from_path<P: AsRef<Path>>(path: P, key: &Key) -> io::Result<SharedReader> {
+		SharedReader::from_file(fs::File::open(path)?, key)
+	}
+	/// Reads the header and directory of an opened PAK file.
+	///
+	/// Returns an error converted from [`Error`](enum.Error.html) if the file does not encode a PAK file.
+	pub fn from_file(file: fs::File, key: &Key) -> io::Result<SharedReader> {
+		let blocks_len = (file.metadata()?.len() / BLOCK_SIZE as u64) as usize;
+		if blocks_len < Header::BLOCKS_LEN {
+			return Err(Error::Truncated.into());
+		}
+		let mut header = Header::zeroed();
+		read_exact_at(&file, 0, header.as_bytes_mut())?;
+		let info = crypt::decrypt_header(&header, key);
+		error::check_info(&info, blocks_len)?;
+		let mut directory = vec![Descriptor::zeroed(); info.directory.size as usize];
+		read_exact_at(&file, info.directory.offset as u64 * BLOCK_SIZE as u64, directory.as_bytes_mut())?;
+		Ok(SharedReader { file, key: *key, info, directory, blocks_len })
+	}
+	/// Unwraps the underlying file.
+	pub fn into_inner(self) -> fs::File {
+		self.file
+	}
+	/// Returns if this SharedReader contains no files or directories.
+	pub fn is_empty(&self) -> bool {
+		self.directory.is_empty()
+	}
+	fn decrypt_desc(&self, index: usize) -> Descriptor {
+		let blocks: &[Block] = self.directory.as_data_view().slice_tail(0);
+		crypt::decrypt_desc_at(blocks, index, &self.info.directory.nonce, &self.key)
+	}
+	/// Finds a descriptor by its path.
+	pub fn find(&self, path: &[u8]) -> Option<Descriptor> {
+		self.find_normalized(&path::normalize(path).ok()?)
+	}
+	fn find_normalized(&self, path: &[u8]) -> Option<Descriptor> {
+		directory::find_by(self.directory.len(), path, self.info.is_sorted(), directory::Case::Sensitive, |i| self.decrypt_desc(i)).map(|(_, desc)| desc)
+	}
+	/// Finds a descriptor by its path.
+	///
+	/// Returns `InvalidPath` if the path is invalid or `NotFound` if no descriptor exists at the given path.
+	pub fn try_find(&self, path: &[u8]) -> Result<Descriptor> {
+		self.find_normalized(&path::normalize(path)?).ok_or(Error::NotFound)
+	}
+	/// Finds a descriptor by its path starting from the given root directory.
+	pub fn find_sub(&self, root: &Descriptor, path: &[u8]) -> Option<Descriptor> {
+		let range = root.section.range_usize();
+		if range.start > range.end || range.end > self.directory.len() {
+			return None;
+		}
+		let path = path::normalize(path).ok()?;
+		directory::find_by(range.len(), &path, self.info.is_sorted(), directory::Case::Sensitive, |i| self.decrypt_desc(range.start + i)).map(|(_, desc)| desc)
+	}
+	/// Finds all the descriptors matching the glob pattern.
+	///
+	/// See [`directory::glob`](directory/fn.glob.html) for more information.
+	pub fn glob(&self, pattern: &[u8]) -> Vec<(Vec<u8>, Descriptor)> {
+		directory::glob_by(self.directory.len(), pattern, |i| self.decrypt_desc(i))
+	}
+	/// Returns if the descriptor is a valid file.
+	///
+	/// See [`MemoryReader::is_valid_file`](struct.MemoryReader.html#method.is_valid_file) for more information.
+	pub fn is_valid_file(&self, desc: &Descriptor) -> bool {
+		desc.content_type != 0 &&
+		desc.section.offset >= Header::BLOCKS_LEN as u32 &&
+		desc.section.offset as usize + desc.section.size as usize <= self.blocks_len &&
+		bytes2blocks(desc.content_size) <= desc.section.size
+	}
+	/// Returns if the descriptor is a valid directory.
+	///
+	/// See [`MemoryReader::is_valid_dir`](struct.MemoryReader.html#method.is_valid_dir) for more information.
+	pub fn is_valid_dir(&self, desc: &Descriptor) -> bool {
+		desc.content_type == 0 &&
+		desc.section.size == desc.content_size &&
+		self.directory.get(desc.section.range_usize()).is_some()
+	}
+	/// Decrypts the contents of the given file descriptor.
+	///
+	/// Returns an error converted from `NotAFile` or `OutOfRange` if the descriptor is not a valid file.
+	pub fn read_data(&self, desc: &Descriptor) -> io::Result<Vec<u8>> {
+		let mut bytes = vec![0; desc.content_size as usize];
+		self.read_into(desc, 0, &mut bytes)?;
+		Ok(bytes)
+	}
+	/// Decrypts the contents of the given file descriptor into the dest buffer.
+	/// Given a byte offset into the file where to start decrypting.
+	///
+	/// Returns an error converted from `NotAFile` or `OutOfRange` if the descriptor is not a valid file or the range is outside the file's section.
+	pub fn read_into(&self, desc: &Descriptor, byte_offset: usize, dest: &mut [u8]) -> io::Result<()> {
+		if !desc.is_file() {
+			return Err(Error::NotAFile.into());
+		}
+		let byte_end = match byte_offset.checked_add(dest.len()) {
+			Some(byte_end) if self.is_valid_file(desc) && byte_end <= desc.section.size as usize * BLOCK_SIZE => byte_end,
+			_ => return Err(Error::OutOfRange.into()),
+		};
+		if dest.is_empty() {
+			return Ok(());
+		}
+		// Read the encrypted blocks covering the requested range
+		let block_start = byte_offset / BLOCK_SIZE;
+		let block_end = byte_end.div_ceil(BLOCK_SIZE);
+		let mut raw = vec![0u8; (block_end - block_start) * BLOCK_SIZE];
+		read_exact_at(&self.file, (desc.section.offset as u64 + block_start as u64) * BLOCK_SIZE as u64, &mut raw)?;
+		let nonce = crypt::counter(&desc.section.nonce, block_start);
+		crypt::decrypt_data(&raw[..], &nonce, &self.key, byte_offset - block_start * BLOCK_SIZE, dest);
+		Ok(())
+	}
+	/// Opens the given file descriptor for streaming its decrypted contents.
+	///
+	/// Returns `None` if the descriptor is not a valid file.
+	pub fn open(&self, desc: &Descriptor) -> Option<SharedFileReader<'_>> {
+		if !self.is_valid_file(desc) {
+			return None;
+		}
+		Some(SharedFileReader { reader: self, desc: *desc, pos: 0, buf: [0; BLOCK_SIZE], buf_index: u64::MAX })
+	}
+}
+
+/// Handle for reading the contents of a file from a [`SharedReader`](struct.SharedReader.html).
+///
+/// Implements `Read`, `Seek` and `BufRead` with positional reads, every handle has its own position.
+#[derive(Clone)]
+pub struct SharedFileReader<'a> {
+	reader: &'a SharedReader,
+	desc: Descriptor,
+	pos: u64,
+	// Single decrypted block backing the BufRead implementation
+	buf: [u8; BLOCK_SIZE],
+	buf_index: u64,
+}
+impl<'a> SharedFileReader<'a> {
+	/// Returns the size of the file in bytes.
+	#[inline]
+	pub fn len(&self) -> u64 {
+		self.desc.content_size as u64
+	}
+	/// Returns if the file is empty.
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.desc.content_size == 0
+	}
+	/// Returns the current position in the file.
+	#[inline]
+	pub fn position(&self) -> u64 {
+		self.pos
+	}
+}
+impl<'a> io::Read for SharedFileReader<'a> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let size = self.len();
+		if self.pos >= size {
+			return Ok(0);
+		}
+		let n = cmp::min(buf.len() as u64, size - self.pos) as usize;
+		self.reader.read_into(&self.desc, self.pos as usize, &mut buf[..n])?;
+		self.pos += n as u64;
+		Ok(n)
+	}
+}
+impl<'a> io::BufRead for SharedFileReader<'a> {
+	fn fill_buf(&mut self) -> io::Result<&[u8]> {
+		let size = self.len();
+		if self.pos >= size {
+			return Ok(&[]);
+		}
+		// Decrypt the block containing the current position if not already available
+		let block_index = self.pos / BLOCK_SIZE as u64;
+		let block_start = block_index * BLOCK_SIZE as u64;
+		let len = (cmp::min(size, block_start + BLOCK_SIZE as u64) - block_start) as usize;
+		if self.buf_index != block_index {
+			self.reader.read_into(&self.desc, block_start as usize, &mut self.buf[..len])?;
+			self.buf_index = block_index;
+		}
+		Ok(&self.buf[(self.pos - block_start) as usize..len])
+	}
+	fn consume(&mut self, amt: usize) {
+		self.pos = cmp::min(self.len(), self.pos + amt as u64);
+	}
+}
+impl<'a> io::Seek for SharedFileReader<'a> {
+	fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+		self.pos = file_reader::seek_pos(self.pos, self.len(), pos)?;
+		Ok(self.pos)
+	}
+}
+
+#[test]
+fn test_shared_threads() {
+	use std::sync::Arc;
+
+	fn assert_send_sync<T: Send + Sync>() {}
+	assert_send_sync::<SharedReader>();
+
+	let key = &[13, 42];
+	let mut edit = MemoryEditor::new();
+	let files: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i; 100 + i as usize]).collect();
+	for (i, content) in files.iter().enumerate() {
+		edit.create_file(format!("assets/{}", i).as_bytes(), content, key);
+	}
+	let (blocks, _) = edit.finish(key);
+
+	let path = std::env::temp_dir().join(format!("pak-shared-{}.pak", std::process::id()));
+	fs::write(&path, blocks.as_bytes()).unwrap();
+	let reader = Arc::new(SharedReader::from_path(&path, key).unwrap());
+	let _ = fs::remove_file(&path);
+
+	let threads: Vec<_> = (0..8).map(|i| {
+		let reader = Arc::clone(&reader);
+		std::thread::spawn(move || {
+			let desc = reader.find(format!("assets/{}", i).as_bytes()).unwrap();
+			let mut tail = [0; 10];
+			reader.read_into(&desc, 90 + i, &mut tail).unwrap();
+			assert_eq!(tail, [i as u8; 10]);
+			reader.read_data(&desc).unwrap()
+		})
+	}).collect();
+	for (thread, content) in threads.into_iter().zip(&files) {
+		assert_eq!(&thread.join().unwrap(), content);
+	}
+
+	let assets = reader.find(b"assets").unwrap();
+	assert!(reader.find_sub(&assets, b"7").is_some());
+	assert_eq!(reader.glob(b"assets/*").len(), 8);
+	assert_eq!(reader.read_data(&assets).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+	// Overflowing ranges are rejected
+	let desc = reader.find(b"assets/3").unwrap();
+	assert_eq!(reader.read_into(&desc, usize::MAX, &mut [0; 2]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+	// Every handle has its own position
+	use std::io::{BufRead, Read, Seek, SeekFrom};
+	let mut first = reader.open(&desc).unwrap();
+	let mut second = reader.open(&desc).unwrap();
+	assert_eq!(first.len(), 103);
+	first.seek(SeekFrom::End(-3)).unwrap();
+	let mut tail = Vec::new();
+	first.read_to_end(&mut tail).unwrap();
+	assert_eq!(tail, [3; 3]);
+	assert_eq!(second.fill_buf().unwrap(), [3; BLOCK_SIZE]);
+	assert!(reader.open(&assets).is_none());
+}
@@ -0,0 +1,203 @@
+/*!
+Verification of PAK files.
+
+Walks the entire directory and checks that the PAK file is consistent, see [`verify`](fn.verify.html).
+*/
+
+use std::{fmt, str};
+use crate::*;
+
+/// Problem found while verifying a PAK file.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Problem {
+	/// The header cannot be decoded.
+	Header(Error),
+	/// A directory claims more children than fit in its parent directory.
+	BadNesting,
+	/// A file descriptor does not pass `MemoryReader::is_valid_file`.
+	InvalidFile,
+	/// A directory descriptor does not pass `MemoryReader::is_valid_dir` or does not address its own children.
+	InvalidDir,
+	/// A file's section overlaps the header.
+	OverlapsHeader,
+	/// A descriptor has an empty name.
+	EmptyName,
+	/// A descriptor has a name containing a path separator or a corrupt name length.
+	BadName,
+}
+impl fmt::Display for Problem {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Problem::Header(err) => write!(f, "invalid header: {}", err),
+			Problem::BadNesting => f.write_str("directory children exceed the parent directory"),
+			Problem::InvalidFile => f.write_str("invalid file descriptor"),
+			Problem::InvalidDir => f.write_str("invalid directory descriptor"),
+			Problem::OverlapsHeader => f.write_str("section overlaps the header"),
+			Problem::EmptyName => f.write_str("empty name"),
+			Problem::BadName => f.write_str("invalid name"),
+		}
+	}
+}
+
+/// A problem found at a specific descriptor.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Issue {
+	/// Index of the descriptor in the directory.
+	///
+	/// Problems with the header have no associated descriptor and use an index of `usize::MAX`.
+	pub index: usize,
+	/// Full path of the descriptor.
+	pub path: Vec<u8>,
+	/// The problem found.
+	pub problem: Problem,
+}
+impl fmt::Display for Issue {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.index == usize::MAX {
+			return write!(f, "{}", self.problem);
+		}
+		let path = str::from_utf8(&self.path).unwrap_or("err");
+		write!(f, "dir[{}] {}: {}", self.index, path, self.problem)
+	}
+}
+
+/// Report of all the problems found while verifying a PAK file.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Report {
+	/// Number of descriptors checked.
+	pub descriptors: usize,
+	/// Every problem found, in directory order.
+	pub issues: Vec<Issue>,
+}
+impl Report {
+	/// Returns if no problems were found.
+	pub fn is_ok(&self) -> bool {
+		self.issues.is_empty()
+	}
+}
+impl fmt::Display for Report {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "checked {} descriptors, found {} problems", self.descriptors, self.issues.len())?;
+		for issue in &self.issues {
+			writeln!(f, "{}", issue)?;
+		}
+		Ok(())
+	}
+}
+
+/// Verifies the PAK file.
+///
+/// The following invariants are checked:
+///
+/// * The header decrypts and the directory fits within the PAK file.
+/// * Directory child counts nest correctly within their parent directories.
+/// * Every file descriptor passes `MemoryReader::is_valid_file` and its section does not overlap the header.
+/// * Every directory descriptor passes `MemoryReader::is_valid_dir` and addresses its own children.
+/// * Names are not empty and do not contain path separators.
+///
+/// Keeps going after finding a problem and reports all of them.
+pub fn verify(blocks: &[Block], key: &Key) -> Report {
+	let mut report = Report::default();
+	let reader = match MemoryReader::try_from_blocks(blocks, key) {
+		Ok(reader) => reader,
+		Err(err) => {
+			report.issues.push(Issue { index: usize::MAX, path: Vec::new(), problem: Problem::Header(err) });
+			return report;
+		},
+	};
+	let header = crypt::decrypt_header(blocks[..Header::BLOCKS_LEN].as_data_view().read::<Header>(0), key);
+	let dir = crypt::decrypt_dir(blocks, &header.directory, key);
+	report.descriptors = dir.len();
+
+	// The end index and path length of every directory being walked
+	let mut stack = vec![(dir.len(), 0)];
+	let mut path = Vec::new();
+	for (i, desc) in dir.iter().enumerate() {
+		while let Some(&(end, _)) = stack.last() {
+			if i < end {
+				break;
+			}
+			stack.pop();
+		}
+		let &(end, path_len) = stack.last().unwrap();
+		path.truncate(path_len);
+		if path_len != 0 {
+			path.push(b'/');
+		}
+		path.extend_from_slice(desc.name());
+
+		let mut problem = |problem| report.issues.push(Issue { index: i, path: path.clone(), problem });
+		if desc.name().is_empty() {
+			problem(Problem::EmptyName);
+		}
+		else if desc.name_len() > 31 || desc.name().iter().any(|&chr| chr == b'/' || chr == b'\\') {
+			problem(Problem::BadName);
+		}
+		if desc.is_dir() {
+			if i + 1 + desc.content_size as usize > end {
+				problem(Problem::BadNesting);
+			}
+			if !reader.is_valid_dir(desc) || desc.section.offset as usize != i + 1 {
+				problem(Problem::InvalidDir);
+			}
+			let next_i = directory::next_sibling(desc, i, end);
+			stack.push((next_i, path.len()));
+		}
+		else if desc.section.offset < Header::BLOCKS_LEN as u32 {
+			problem(Problem::OverlapsHeader);
+		}
+		else if !reader.is_valid_file(desc) {
+			problem(Problem::InvalidFile);
+		}
+	}
+	report
+}
+
+// Decrypts the directory, lets tests corrupt it and encrypts it back in place
+#[cfg(test)]
+pub(crate) fn rewrite_dir<F: FnOnce(&mut Vec<Descriptor>)>(blocks: &mut [Block], key: &Key, f: F) {
+	let header = crypt::decrypt_header(blocks[..Header::BLOCKS_LEN].as_data_view().read::<Header>(0), key);
+	let mut dir = crypt::decrypt_dir(blocks, &header.directory, key);
+	f(&mut dir);
+	let offset = header.directory.offset as usize;
+	crypt::encrypt(dir.as_data_view().slice_tail(0), &header.directory.nonce, key, &mut blocks[offset..offset + dir.len() * Descriptor::BLOCKS_LEN]);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_verify() {
+		let key = &[13, 42];
+		let mut edit = MemoryEditor::new();
+		edit.create_file(b"a/b/file", b"content", key);
+		edit.create_file(b"a/other", b"content", key);
+		edit.create_dir(b"empty");
+		let (mut blocks, _) = edit.finish(key);
+
+		let report = verify(&blocks, key);
+		assert!(report.is_ok(), "{}", report);
+		assert_eq!(report.descriptors, 5);
+
+		rewrite_dir(&mut blocks, key, |dir| {
+			dir[1].content_size = 10;
+			dir[2].section.offset = 2;
+			dir[3].set_name(b"x/y");
+			dir[4].set_name(b"");
+		});
+		let report = verify(&blocks, key);
+		let problems: Vec<_> = report.issues.iter().map(|issue| (issue.index, issue.problem)).collect();
+		assert_eq!(problems, [
+			(1, Problem::BadNesting),
+			(1, Problem::InvalidDir),
+			(2, Problem::OverlapsHeader),
+			(3, Problem::BadName),
+			(4, Problem::EmptyName),
+		]);
+		assert_eq!(report.issues[2].path, b"a/b/file");
+
+		let report = verify(&blocks, &[42, 13]);
+		assert_eq!(report.issues[0].problem, Problem::Header(Error::BadKey));
+	}
+}